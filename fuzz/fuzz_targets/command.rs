@@ -0,0 +1,33 @@
+//! Apply any line of the user file on a small sheet
+//! Run with `cargo +nightly fuzz run command` from the root of the repository
+
+#![no_main]
+
+use defi2::{Error, SpreadSheet};
+use libfuzzer_sys::fuzz_target;
+
+/// The view of the sheet as written in the "view.csv" format
+fn view(sheet: &SpreadSheet) -> Vec<u8> {
+    let mut view = Vec::new();
+    sheet.write_view(&mut view).unwrap();
+    view
+}
+
+fuzz_target!(|line: &str| {
+    let mut sheet = SpreadSheet::new();
+    sheet.read_data("1;2\n=#(0,0,0,1,>0);=SUM(A1:B1)\n".as_bytes()).unwrap();
+    sheet.evaluate();
+    let before = view(&sheet);
+
+    // A line is rejected when it can't be parsed, or when it writes beyond the last row or column
+    // A rejected line changes nothing
+    match sheet.apply_line(line) {
+        Ok(()) => {}
+        Err(Error::Parse { .. }) | Err(Error::OutOfBounds { .. }) => {
+            assert!(sheet.take_changes().is_empty());
+            assert_eq!(view(&sheet), before);
+        }
+        Err(error) => panic!("unexpected error {}", error),
+    }
+    sheet.take_changes();
+});
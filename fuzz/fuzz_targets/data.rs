@@ -0,0 +1,17 @@
+//! Read any data file, evaluate it and print its view
+//! Run with `cargo +nightly fuzz run data` from the root of the repository
+
+#![no_main]
+
+use defi2::SpreadSheet;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut sheet = SpreadSheet::new();
+    // A file which is not text is an Io error, nothing more
+    if sheet.read_data(data).is_err() {
+        return;
+    }
+    sheet.evaluate();
+    sheet.write_view(std::io::sink()).unwrap();
+});
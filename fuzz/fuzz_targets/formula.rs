@@ -0,0 +1,27 @@
+//! Read any content of the data file as a sheet of one cell
+//! Run with `cargo +nightly fuzz run formula` from the root of the repository
+
+#![no_main]
+
+use defi2::{CellError, Coordinates, Error, SpreadSheet};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|content: &str| {
+    // A separator or a line break would make more than one cell
+    if content.contains(|c| c == ';' || c == '\n' || c == '\r') || content.trim().is_empty() {
+        return;
+    }
+    let mut sheet = SpreadSheet::new();
+    sheet.read_data(content.as_bytes()).unwrap();
+    sheet.evaluate();
+    let cell = Coordinates::from(0, 0);
+    let faulty = sheet.get_error(&cell) == Some(CellError::Syntax);
+
+    // A content is a syntax error exactly when the user can't write it either
+    let mut written = SpreadSheet::new();
+    match written.apply_line(&format!("0 0 {}", content)) {
+        Ok(()) => assert!(!faulty),
+        Err(Error::Parse { .. }) => assert!(faulty),
+        Err(error) => panic!("unexpected error {}", error),
+    }
+});
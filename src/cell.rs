@@ -1,15 +1,13 @@
 use crate::coordinates::Coordinates;
 use crate::rectangle::Rectangle;
-use petgraph::graph::NodeIndex;
-use crate::rules::Rules;
+use crate::parser::{Datum, Expr, Function, Predicate, VALUE_MAX};
+use std::fmt;
 
 /// Structure of a cell
-/// Index : representing the index of node where the cell will be residing
 /// Coordinates : reprente the coordinates of the cell by (row, collumn)
 /// Category : the cell type
-#[derive(PartialOrd, PartialEq, Default, Debug, Clone, Copy)]
+#[derive(PartialOrd, PartialEq, Default, Debug, Clone)]
 pub struct Cell {
-    pub index: NodeIndex<u32>,
     pub coordinates: Coordinates,
     pub category: Category
 }
@@ -17,18 +15,73 @@ pub struct Cell {
 /// Enum of cell's categories
 /// A cell is either
 /// StaticCell which containt only a value
-/// OccurCell which is a cell that will count the occurence of it value in a certain area
-/// FaultyCell
-#[derive(PartialOrd, PartialEq, Debug, Clone, Copy)]
+/// OccurCell which is a cell that will count the cells matching its predicate in a certain area
+/// AggregateCell which is a cell computing a function (sum, min, max, average) over a certain area
+/// ExprCell which is a cell computing an arithmetic expression
+/// FaultyCell which carry the reason of its failure
+// The variants are named after the structs they hold
+#[allow(clippy::enum_variant_names)]
+#[derive(PartialOrd, PartialEq, Debug, Clone)]
 pub enum Category {
     StaticCell(StaticCell),
     OccurCell(OccurCell),
-    FaultyCell,
+    AggregateCell(AggregateCell),
+    ExprCell(ExprCell),
+    FaultyCell(CellError),
 }
 
 impl Default for Category {
     fn default() -> Category {
-        Category::FaultyCell
+        Category::FaultyCell(CellError::Syntax)
+    }
+}
+
+/// Enum of the reasons a cell can't be evaluated
+/// Syntax : the datum is not a value nor a well formed formula
+/// Range : an area of the formula is not a rectangle, like r2 < r1
+/// Cycle : the cell is part of a cycle
+/// Arithmetic : a division by zero, or a result below 0 or above 255
+/// Propagated : the cell reads another faulty cell
+#[derive(PartialOrd, PartialEq, Debug, Clone, Copy)]
+pub enum CellError {
+    Syntax,
+    Range,
+    Cycle,
+    Arithmetic,
+    Propagated,
+}
+
+/// How a formula reading a faulty cell is evaluated
+/// Propagate : the formula becomes faulty too, with the Propagated error
+/// Skip : the faulty cell is left out of occurrences and aggregates, only a direct reference fails
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Propagation {
+    #[default]
+    Propagate,
+    Skip,
+}
+
+impl Propagation {
+
+    pub fn from_name(name: &str) -> Option<Propagation> {
+        match name.to_ascii_lowercase().as_str() {
+            "propagate" => Some(Propagation::Propagate),
+            "skip" => Some(Propagation::Skip),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CellError::Syntax => "#SYNTAX",
+            CellError::Range => "#RANGE",
+            CellError::Cycle => "#CYCLE",
+            CellError::Arithmetic => "#ARITH",
+            CellError::Propagated => "#PROPAGATED",
+        };
+        write!(f, "{}", name)
     }
 }
 
@@ -40,43 +93,61 @@ pub struct StaticCell {
 
 /// Structure of a dynamic cell
 /// OccurCell contain a rectangle which represent the area where this cell will be counting it occurrence
-#[derive(PartialOrd, PartialEq, Default, Debug, Clone, Copy)]
+/// The predicate is the condition a value has to match to be an occurrence
+/// The occurrence is the true count, its value is kept under 255 only when read
+#[derive(PartialOrd, PartialEq, Debug, Clone, Copy)]
 pub struct OccurCell {
     pub occurrence: u32,
     pub rectangle: Rectangle,
-    pub value: u32,
+    pub predicate: Predicate,
+}
+
+/// Structure of an aggregate cell
+/// AggregateCell contain a rectangle which represent the area where the function is computed
+/// The sum, the count and the extremum (min or max) of the valid values of the area
+/// are kept to update the result without browsing it again
+/// The result is an error when a cell of the area is faulty, unless faulty cells are skipped,
+/// or when it is above 255
+#[derive(PartialOrd, PartialEq, Debug, Clone, Copy)]
+pub struct AggregateCell {
+    pub function: Function,
+    pub rectangle: Rectangle,
+    pub sum: u64,
+    pub count: u64,
+    pub extremum: u32,
+    pub result: Result<u32, CellError>,
+}
+
+/// Structure of an expression cell
+/// ExprCell contain the parsed expression and the result of its last evaluation
+/// The result is an error when the evaluation failed, like a division by zero or a result outside 0..255
+#[derive(PartialOrd, PartialEq, Debug, Clone)]
+pub struct ExprCell {
+    pub expr: Expr,
+    pub result: Result<u32, CellError>,
 }
 
 impl Cell {
 
     pub fn new() -> Cell{
         Cell {
-            index: NodeIndex::new(0),
             coordinates: Coordinates::new(),
-            category: Category::FaultyCell,
-        }
-    }
-
-    pub fn from(index: NodeIndex, coordinates: Coordinates, category: Category ) -> Cell {
-        Cell {
-            index,
-            coordinates,
-            category
+            category: Category::default(),
         }
     }
 
-    /// Return a build cell from formula
-    pub fn from_formula(formula: &String, rule: &Rules) -> Category {
-        if OccurCell::check_formula(formula, rule).unwrap() {
-            let mut cell = OccurCell::new();
-            cell.parse_formula(formula).unwrap();
-            return Category::OccurCell(cell);
-        } else if StaticCell::check_number(formula, rule).unwrap() {
-            let mut cell = StaticCell::new();
-            cell.value = formula.parse().unwrap();
-            return Category::StaticCell(cell);
+    /// Return a build cell from an already parsed datum
+    pub fn from_datum(datum: &Datum) -> Category {
+        match datum {
+            Datum::Value(value) => Category::StaticCell(StaticCell::from(*value)),
+            Datum::Formula(Expr::Occur(rectangle, predicate)) => {
+                Category::OccurCell(OccurCell::from(*rectangle, *predicate))
+            }
+            Datum::Formula(Expr::Aggregate(function, rectangle)) => {
+                Category::AggregateCell(AggregateCell::from(*function, *rectangle))
+            }
+            Datum::Formula(expr) => Category::ExprCell(ExprCell::from(expr.clone())),
         }
-        return Category::FaultyCell;
     }
 
     pub fn set_coordinates(&mut self, coordinates: Coordinates){
@@ -84,86 +155,70 @@ impl Cell {
     }
 
     pub fn set_category(&mut self, new_category: Category){
-
-        if let Category::OccurCell(occ_cell) = new_category {
-            if ! occ_cell.rectangle.is_valid() {
-                self.category = Category::FaultyCell;
-            } else {
-                self.category = new_category;
-            }
-        } else {
-            self.category = new_category;
-        }
+        self.category = new_category.checked();
     }
 
-    pub fn set_index(&mut self, index: NodeIndex<u32>){
-        self.index = index;
-    }
-
-    pub fn get_index(&mut self) -> NodeIndex<u32> { return  self.index;}
-
-    pub fn get_value(&mut self) -> Option<u32> {
+    /// Return every area read by the cell, empty if it is not a formula
+    pub fn get_areas(&self) -> Vec<Rectangle> {
+        let mut areas = Vec::new();
         match self.category {
-            Category::OccurCell(occ) => Some(occ.value),
-            Category::StaticCell(stat) => Some(stat.value),
-            _ => None
+            Category::OccurCell(occ) => areas.push(occ.rectangle),
+            Category::AggregateCell(agg) => areas.push(agg.rectangle),
+            Category::ExprCell(ref expr_cell) => expr_cell.expr.areas(&mut areas),
+            _ => {}
         }
+        areas
     }
 
-    pub fn set_occurrence(&mut self, occurrence: u32) {
-        if let Category::OccurCell(mut occ) = self.category {
-            occ.occurrence = occurrence;
-            self.category = Category::OccurCell(occ);
-        }
-    }
-
-    pub fn get_occurrence(&mut self) -> Option<u32>{
-        match self.category {
-            Category::OccurCell(occ) => Some(occ.occurrence),
-            _ => None
-        }
+    /// Return true if the cell has to be evaluated, which mean OccurCells, AggregateCells and ExprCells
+    pub fn is_formula(&self) -> bool {
+        matches!(self.category, Category::OccurCell(_) | Category::AggregateCell(_) | Category::ExprCell(_))
     }
+}
 
-    pub fn get_coordinates(&mut self) -> Coordinates {
-        return self.coordinates;
+/// Return the value to print, a faulty cell is "P" or its error when show_errors is set
+pub fn result_string(result: Result<u32, CellError>, show_errors: bool) -> String {
+    match result {
+        Ok(value) => value.to_string(),
+        Err(error) if show_errors => error.to_string(),
+        Err(_) => "P".to_string()
     }
+}
 
-    pub fn decrement_occ(&mut self) {
-        match self.category {
-            Category::OccurCell(mut cell) => {
-                cell.occurrence -= 1;
-                self.category = Category::OccurCell(cell);
-            },
-            _ => {},
-        }
-    }
+impl Category {
 
-    pub fn increment_occ(&mut self) {
-        match self.category {
-            Category::OccurCell(mut cell) => {
-                cell.occurrence += 1;
-                self.category = Category::OccurCell(cell);
-            },
-            _ => {},
+    /// Return the same category, unless the formula reads an invalid rectangle
+    pub fn checked(self) -> Category {
+        match self {
+            Category::OccurCell(occ_cell) if ! occ_cell.rectangle.is_valid() => {
+                Category::FaultyCell(CellError::Range)
+            }
+            Category::AggregateCell(agg_cell) if ! agg_cell.rectangle.is_valid() => {
+                Category::FaultyCell(CellError::Range)
+            }
+            Category::ExprCell(ref expr_cell) if ! expr_cell.is_valid() => {
+                Category::FaultyCell(CellError::Range)
+            }
+            category => category,
         }
     }
 
-    pub fn get_value_string(&mut self) -> String {
-        match self.get_special() {
-            Some(value) => value.to_string(),
-            None => "P".to_string()
+    /// Return the value of a cell of this category, or the reason why it has none
+    pub fn get_result(&self) -> Result<u32, CellError> {
+        match *self {
+            Category::StaticCell(cell) => Ok(cell.value),
+            Category::OccurCell(cell) => Ok(cell.occurrence.min(VALUE_MAX)),
+            Category::AggregateCell(cell) => cell.result,
+            Category::ExprCell(ref cell) => cell.result,
+            Category::FaultyCell(error) => Err(error),
         }
     }
+}
 
-    pub fn get_special(&self) -> Option<u32> {
-        match self.category {
-            Category::StaticCell(cell) => Some(cell.value),
-            Category::OccurCell(cell) => Some(cell.occurrence),
-            Category::FaultyCell => None,
-        }
+impl Default for StaticCell {
+    fn default() -> Self {
+        Self::new()
     }
-
-
 }
 
 impl StaticCell {
@@ -177,53 +232,118 @@ impl StaticCell {
             value
         }
     }
+}
 
-    pub fn check_number(number: &String, rule: &Rules) -> std::io::Result<bool> {
-        let mut trimmed_str = String::from(number);
-        trimmed_str.retain(|c| !c.is_whitespace());
-        Ok(rule.value_regex.is_match(&trimmed_str))
+impl Default for OccurCell {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl OccurCell {
 
     pub fn new() -> OccurCell {
+        OccurCell::from(Rectangle::new(), Predicate::Equal(0))
+    }
+
+    pub fn from(rectangle: Rectangle, predicate: Predicate) -> OccurCell {
         OccurCell {
             occurrence: 0,
-            rectangle: Rectangle::new(),
-            value: 0
+            rectangle,
+            predicate
         }
     }
+}
 
-    pub fn from(rectangle: Rectangle) -> OccurCell {
-        OccurCell {
-            occurrence: 0,
+impl AggregateCell {
+
+    pub fn from(function: Function, rectangle: Rectangle) -> AggregateCell {
+        AggregateCell {
+            function,
             rectangle,
-            value: 0
+            sum: 0,
+            count: 0,
+            extremum: 0,
+            result: Err(CellError::Propagated)
         }
     }
 
-    /// check if the formula respect the good syntax
-    pub fn check_formula(formula: &String, rule: &Rules) -> std::io::Result<bool> {
-        let mut trimmed_str = String::from(formula);
-        trimmed_str.retain(|c| !c.is_whitespace());
-        Ok(rule.occur_regex.is_match(&trimmed_str))
+    /// Compute the function from every value of the area
+    /// A None value is a faulty cell, with the Propagate policy the whole aggregate is then faulty,
+    /// with the Skip policy the faulty cell is left out
+    pub fn fill(&mut self, values: &[Option<u32>], propagation: Propagation) {
+        if propagation == Propagation::Propagate && values.iter().any(|value| value.is_none()) {
+            self.result = Err(CellError::Propagated);
+            return;
+        }
+
+        let values: Vec<u32> = values.iter().flatten().cloned().collect();
+        self.sum = values.iter().map(|&value| value as u64).sum();
+        self.count = values.len() as u64;
+        self.extremum = match self.function {
+            Function::Max => values.iter().cloned().max().unwrap_or(0),
+            _ => values.iter().cloned().min().unwrap_or(0),
+        };
+        self.refresh();
     }
 
-    /// parse the cell from a formula
-    pub fn parse_formula(&mut self, formula: &String) -> std::io::Result<bool> {
-        let mut trimmed_str = String::from(formula);
-        trimmed_str.retain(|c| !c.is_whitespace());
+    /// Update the function when one value of the area goes from old to new, a None value being left out
+    /// Return false if it can't be done without browsing the area again,
+    /// which happen when the min (or max) value is leaving
+    pub fn update(&mut self, old: Option<u32>, new: Option<u32>) -> bool {
+        if let Some(old) = old {
+            self.sum -= old as u64;
+            self.count -= 1;
+        }
+        if let Some(new) = new {
+            self.sum += new as u64;
+            self.count += 1;
+        }
 
-        let slice = &trimmed_str[3..trimmed_str.len() - 1];
-        let splited: Vec<&str> = slice.split(",").collect();
+        match (self.function, old, new) {
+            _ if self.count == 0 => self.extremum = 0,
+            (Function::Min, _, Some(new)) if new <= self.extremum || self.count == 1 => self.extremum = new,
+            (Function::Min, Some(old), _) if old == self.extremum => return false,
+            (Function::Max, _, Some(new)) if new >= self.extremum || self.count == 1 => self.extremum = new,
+            (Function::Max, Some(old), _) if old == self.extremum => return false,
+            _ => {}
+        }
+        self.refresh();
+        true
+    }
 
-        let c1 = (splited[0].parse::<u32>().unwrap(), splited[1].parse::<u32>().unwrap());
-        let c2  = (splited[2].parse::<u32>().unwrap(), splited[3].parse::<u32>().unwrap());
-        self.rectangle = Rectangle::from(Coordinates::from(c1.0,c1.1),
-                                         Coordinates::from(c2.0,c2.1));
-        self.value = splited[4].parse::<u32>().unwrap();
+    /// Value of the function from the sum, the count and the extremum
+    /// The average of no value at all is an arithmetic error
+    pub fn compute(&self) -> Result<u64, CellError> {
+        match self.function {
+            Function::Sum => Ok(self.sum),
+            Function::Average => self.sum.checked_div(self.count).ok_or(CellError::Arithmetic),
+            Function::Min | Function::Max => Ok(self.extremum as u64),
+        }
+    }
+
+    /// Update the result, a value above 255 is an arithmetic error as for an expression cell
+    fn refresh(&mut self) {
+        self.result = self.compute().and_then(|value| match value {
+            value if value <= VALUE_MAX as u64 => Ok(value as u32),
+            _ => Err(CellError::Arithmetic),
+        });
+    }
+}
+
+impl ExprCell {
+
+    pub fn from(expr: Expr) -> ExprCell {
+        ExprCell {
+            expr,
+            result: Err(CellError::Propagated)
+        }
+    }
 
-        Ok(true)
+    /// An expression is valid if every area it reads is a valid rectangle
+    pub fn is_valid(&self) -> bool {
+        let mut areas = Vec::new();
+        self.expr.areas(&mut areas);
+        areas.iter().all(|area| area.is_valid())
     }
 }
@@ -1,91 +1,90 @@
-use std::io::*;
-use std::fs::File;
-use regex::Regex;
+use std::io::BufRead;
+use crate::error::Error;
+use crate::grid::COORDINATE_MAX;
+use crate::parser::{self, ParseError, VALUE_MAX};
 
-/// Verifie l'extension des fichiers.
+/// Verifie que le fichier data correspond à ce qui est attendu.
 ///
-/// Plus précisément, verifie que chaque fichier est à son bon index
-/// dans la ligne d'argument.
+/// Par attendu, on entend un entier, une formule ou une case vide.
+/// Renvoie une erreur par valeur invalide, avec sa ligne.
 ///
 /// # Examples
 ///
+/// ```text
+/// 1;=#(0,0,0,1,>0);;=SUM(A1:B1)
 /// ```
-/// *.csv *.txt *.csv *.txt
-/// ```
-pub fn check_extension(vector: &mut Vec<&String>) -> std::io::Result<bool> {
-    for i in 1..(vector.len()) {
-        let file_name = vector[i];
-        let tokens: Vec<&str> = file_name.split(".").collect();
-        if (i % 2 == 0 && tokens[1] != "txt") || (i % 2 != 0 && tokens[1] != "csv") {
-            return Ok(false);
-        }
-    }
-    return Ok(true);
-}
-
-/// Verifie que le fichier data correspond à ce qui est attendu.
-///
-/// Par attendu, on entend un entier ou une formule.
-pub fn check_data_file(file_name: &String) -> std::io::Result<bool> {
-    let file = File::open(file_name)?;
-    let reader = BufReader::new(file);
-
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"^((=#\((\s*\d+\s*,){4}(1?[0-9]{1,2}|2[0-4][0-9]|25[0-5])\)$)|(1?[0-9]{1,2}|2[0-4][0-9]|25[0-5]))$").unwrap();
-    }
+pub fn check_data<R: BufRead>(reader: R) -> Result<Vec<Error>, Error> {
+    let mut errors = Vec::new();
 
-    for line in reader.lines() {
-        for element in line.unwrap().split(";") {
-            let mut element_trim = String::from(element);
-            element_trim.retain(|c| !c.is_whitespace());
-            if !RE.is_match(&element_trim) {
-                return Ok(false);
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let mut start = 0;
+        for element in line.split(';') {
+            if !element.trim().is_empty() {
+                if let Err(mut error) = parser::parse_datum(element) {
+                    // La colonne est donnée dans la ligne, et non dans la valeur
+                    error.column += start;
+                    errors.push(Error::from(error).at_line(number + 1));
+                }
             }
+            start += element.len() + 1;
         }
     }
 
-    println!("OK !");
-
-    return Ok(true);
+    Ok(errors)
 }
 
 /// Verifie que le fichier user correspond à ce qui est attendu.
 ///
 /// Par attendu, on entend des lignes composées de:
-/// entier entier (formule ou entier)
-pub fn check_user_file(file_name: &String) -> std::io::Result<bool> {
-    let file = File::open(file_name)?;
-    let reader = BufReader::new(file);
-
-    lazy_static! {
-        static ref RE_LINE: Regex = Regex::new(r"^(\d+) (\d+) (=#\((\s*\d+\s*,){4}\s*(1?[0-9]{1,2}|2[0-4][0-9]|25[0-5])\s*\)|(1?[0-9]{1,2}|2[0-4][0-9]|25[0-5]))$").unwrap();
-    }
+/// entier entier (formule ou entier), ou référence (formule ou entier).
+/// Renvoie une erreur par ligne invalide, avec son numéro.
+///
+/// # Examples
+///
+/// ```text
+/// 0 1 =SUM(0,0,2,2)
+/// B2 12
+/// ```
+pub fn check_user<R: BufRead>(reader: R) -> Result<Vec<Error>, Error> {
+    let mut errors = Vec::new();
 
-    for line in reader.lines() {
-        match RE_LINE.captures(&line.unwrap()) {
-            Some(_x) => {}
-            None => return Ok(false)
-        }
+    for (number, line) in reader.lines().enumerate() {
+        let error = match parser::parse_command(&line?) {
+            Err(error) => Error::from(error),
+            Ok(command) if command.coordinates.row > COORDINATE_MAX || command.coordinates.col > COORDINATE_MAX => {
+                Error::OutOfBounds { file: None, line: None, coordinates: command.coordinates }
+            }
+            Ok(_) => continue,
+        };
+        errors.push(error.at_line(number + 1));
     }
 
-    return Ok(true);
+    Ok(errors)
 }
 
 /// Verifie que le fichier view correspond à ce qui est attendu.
 ///
-/// En cours de refonte.
-pub fn check_view_file(filename: &String) -> std::io::Result<bool> {
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
-    let int_reg = Regex::new(r"\d+").unwrap();
+/// Par attendu, on entend des entiers entre 0 et 255, des `P` ou des cases vides,
+/// séparés par des ';'.
+/// Renvoie une erreur par valeur invalide, avec sa ligne.
+pub fn check_view<R: BufRead>(reader: R) -> Result<Vec<Error>, Error> {
+    let mut errors = Vec::new();
 
-    for line in reader.lines() {
-        for elt in line.unwrap().split(" ") {
-            if !int_reg.is_match(&elt) && elt != "P" {
-                return Ok(false);
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let mut start = 0;
+        for element in line.split(';') {
+            let valid = element.is_empty()
+                || element == "P"
+                || element.parse::<u32>().is_ok_and(|value| value <= VALUE_MAX);
+            if !valid {
+                let error = ParseError::new(start + 1, &format!("expected a value or P, found {:?}", element));
+                errors.push(Error::from(error).at_line(number + 1));
             }
+            start += element.len() + 1;
         }
     }
 
-    return Ok(true);
+    Ok(errors)
 }
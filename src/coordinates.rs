@@ -23,6 +23,97 @@ impl Coordinates {
         }
     }
 
+    /// Parse a reference written in A1 or R1C1 notation
+    pub fn parse(reference: &str) -> Option<Coordinates> {
+        Coordinates::from_a1(reference).or_else(|| Coordinates::from_r1c1(reference))
+    }
+
+    /// Parse a reference like `B7`
+    /// Columns are letters starting at `A`, rows are numbers starting at 1
+    /// So `A1` is (0, 0) and `B7` is (6, 1)
+    pub fn from_a1(reference: &str) -> Option<Coordinates> {
+        let split = reference.find(|c: char| c.is_ascii_digit())?;
+        let (letters, digits) = reference.split_at(split);
+        if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+
+        let mut col: u32 = 0;
+        for letter in letters.chars() {
+            let letter = letter.to_ascii_uppercase() as u32 - 'A' as u32 + 1;
+            col = col.checked_mul(26)?.checked_add(letter)?;
+        }
+        let row = parse_one_based(digits)?;
+        Some(Coordinates::from(row, col - 1))
+    }
+
+    /// Parse a reference like `R7C2`, rows and columns are numbers starting at 1
+    pub fn from_r1c1(reference: &str) -> Option<Coordinates> {
+        let upper = reference.to_ascii_uppercase();
+        let rest = upper.strip_prefix('R')?;
+        let split = rest.find('C')?;
+        let row = parse_one_based(&rest[..split])?;
+        let col = parse_one_based(&rest[split + 1..])?;
+        Some(Coordinates::from(row, col))
+    }
+
+    /// Format the coordinates like `B7`
+    pub fn to_a1(&self) -> String {
+        let mut letters = Vec::new();
+        let mut col = self.col as u64 + 1;
+        while col > 0 {
+            let letter = ((col - 1) % 26) as u8;
+            letters.push((b'A' + letter) as char);
+            col = (col - 1) / 26;
+        }
+        letters.iter().rev().collect::<String>() + &(self.row as u64 + 1).to_string()
+    }
+
+    /// Format the coordinates like `R7C2`
+    pub fn to_r1c1(&self) -> String {
+        format!("R{}C{}", self.row as u64 + 1, self.col as u64 + 1)
+    }
+}
+
+/// Parse a number starting at 1 and return it starting at 0
+fn parse_one_based(digits: &str) -> Option<u32> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let number: u32 = digits.parse().ok()?;
+    number.checked_sub(1)
+}
+
+/// Way coordinates are written in the outputs
+/// Numeric : `6 1`, the format of the specification
+/// A1 : `B7`
+/// R1C1 : `R7C2`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Notation {
+    #[default]
+    Numeric,
+    A1,
+    R1C1,
+}
+
+impl Notation {
+
+    pub fn from_name(name: &str) -> Option<Notation> {
+        match name.to_ascii_lowercase().as_str() {
+            "numeric" => Some(Notation::Numeric),
+            "a1" => Some(Notation::A1),
+            "r1c1" => Some(Notation::R1C1),
+            _ => None,
+        }
+    }
+
+    pub fn format(&self, coordinates: &Coordinates) -> String {
+        match self {
+            Notation::Numeric => format!("{} {}", coordinates.row, coordinates.col),
+            Notation::A1 => coordinates.to_a1(),
+            Notation::R1C1 => coordinates.to_r1c1(),
+        }
+    }
 }
 
 impl PartialEq for Coordinates {
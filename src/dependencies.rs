@@ -0,0 +1,80 @@
+extern crate rstar;
+use crate::coordinates::Coordinates;
+use crate::rectangle::Rectangle;
+use self::rstar::RTree;
+use self::rstar::primitives::{GeomWithData, Rectangle as Envelope};
+use std::collections::HashMap;
+
+/// An area read by a formula cell, stored in the R-tree with the coordinates of the formula
+type Area = GeomWithData<Envelope<[f64; 2]>, Coordinates>;
+
+/// Dependencies between the cells, stored as the areas read by every formula
+/// areas : the areas read by each formula cell
+/// tree : the same areas in an R-tree, to find the formulas reading a cell without browsing them all
+///
+/// A formula reading a rectangle costs one entry, whatever the size of the rectangle
+#[derive(Default, Debug, Clone)]
+pub struct Dependencies {
+    areas: HashMap<Coordinates, Vec<Rectangle>>,
+    tree: RTree<Area>,
+}
+
+impl Dependencies {
+
+    pub fn new() -> Dependencies {
+        Dependencies {
+            areas: HashMap::new(),
+            tree: RTree::new(),
+        }
+    }
+
+    /// A cell is a square of side 1 in the R-tree, its coordinates are doubled to stay whole numbers
+    /// An area of a single cell is then not flat, flat areas make the R-tree slow to search
+    /// The coordinates are floats, the surface of the largest area would overflow an integer
+    fn to_area(father: Coordinates, rectangle: &Rectangle) -> Area {
+        let begin = [2.0 * rectangle.begin.row as f64, 2.0 * rectangle.begin.col as f64];
+        let end = [2.0 * rectangle.end.row as f64 + 1.0, 2.0 * rectangle.end.col as f64 + 1.0];
+        GeomWithData::new(Envelope::from_corners(begin, end), father)
+    }
+
+    /// Record the areas read by a formula cell, replacing the ones it was reading
+    pub fn insert(&mut self, father: Coordinates, areas: Vec<Rectangle>) {
+        self.remove(father);
+        for rectangle in &areas {
+            self.tree.insert(Dependencies::to_area(father, rectangle));
+        }
+        self.areas.insert(father, areas);
+    }
+
+    /// Forget the areas read by a cell, nothing happens if it was reading none
+    pub fn remove(&mut self, father: Coordinates) {
+        if let Some(areas) = self.areas.remove(&father) {
+            for rectangle in &areas {
+                self.tree.remove(&Dependencies::to_area(father, rectangle));
+            }
+        }
+    }
+
+    /// Areas read by a cell, empty if it is not a formula
+    pub fn areas(&self, father: Coordinates) -> &[Rectangle] {
+        self.areas.get(&father).map_or(&[], |areas| areas.as_slice())
+    }
+
+    /// Every cell reading some areas
+    pub fn formulas(&self) -> impl Iterator<Item = Coordinates> + '_ {
+        self.areas.keys().copied()
+    }
+
+    /// Every cell reading the cell at these coordinates, each one once and in row order
+    pub fn fathers(&self, coordinates: &Coordinates) -> Vec<Coordinates> {
+        let point = [2.0 * coordinates.row as f64, 2.0 * coordinates.col as f64];
+        let mut fathers: Vec<Coordinates> = self.tree
+            .locate_all_at_point(&point)
+            .map(|area| area.data)
+            .collect();
+        // A formula can read the same cell through two areas
+        fathers.sort();
+        fathers.dedup();
+        fathers
+    }
+}
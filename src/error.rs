@@ -0,0 +1,98 @@
+use crate::coordinates::Coordinates;
+use crate::parser::ParseError;
+use std::fmt;
+use std::io;
+
+/// Enum of the reasons the spreadsheet can't go on
+/// Io : a file or a stream could not be read or written
+/// Parse : a line of the user file is not a command, with the file and the line when they are known
+/// OutOfBounds : a command writes a cell beyond the last row or column a sheet can hold,
+/// with the file and the line when they are known
+/// Usage : the arguments of the command line are wrong
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse {
+        file: Option<String>,
+        line: Option<usize>,
+        error: ParseError,
+    },
+    OutOfBounds {
+        file: Option<String>,
+        line: Option<usize>,
+        coordinates: Coordinates,
+    },
+    Usage(String),
+}
+
+impl Error {
+
+    /// Tell on which line, starting at 1, a wrong command was found
+    pub fn at_line(self, line: usize) -> Error {
+        match self {
+            Error::Parse { file, error, .. } => Error::Parse { file, line: Some(line), error },
+            Error::OutOfBounds { file, coordinates, .. } => Error::OutOfBounds { file, line: Some(line), coordinates },
+            error => error,
+        }
+    }
+
+    /// Tell in which file a wrong command was found
+    pub fn in_file(self, path: &str) -> Error {
+        let file = Some(path.to_string());
+        match self {
+            Error::Parse { line, error, .. } => Error::Parse { file, line, error },
+            Error::OutOfBounds { line, coordinates, .. } => Error::OutOfBounds { file, line, coordinates },
+            error => error,
+        }
+    }
+}
+
+/// Write where a wrong command was found, as much as it is known
+fn write_location(f: &mut fmt::Formatter, file: &Option<String>, line: &Option<usize>) -> fmt::Result {
+    if let Some(file) = file {
+        write!(f, "{}, ", file)?;
+    }
+    if let Some(line) = line {
+        write!(f, "line {}, ", line)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Parse { file, line, error } => {
+                write_location(f, file, line)?;
+                write!(f, "{}", error)
+            }
+            Error::OutOfBounds { file, line, coordinates } => {
+                write_location(f, file, line)?;
+                write!(f, "cell {} {} is beyond the last row or column", coordinates.row, coordinates.col)
+            }
+            Error::Usage(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Parse { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Error {
+        Error::Parse { file: None, line: None, error }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::cell::{result_string, CellError};
+use crate::error::Error;
+
+/// Way the outputs are written
+/// Text : the formats of the specification, and the DOT language for the dependency graph
+/// Json : one JSON value per line, a cell result being a number, an error string, or null when empty
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+impl Format {
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    /// Return a problem found in a file as a line of the output
+    pub fn problem_string(&self, problem: &Error) -> String {
+        match self {
+            Format::Text => problem.to_string(),
+            Format::Json => format!("{{\"error\":{}}}", quote(&problem.to_string())),
+        }
+    }
+}
+
+/// Return the string quoted and escaped as a JSON string
+pub fn quote(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Return the JSON value of a cell result, null for an empty cell
+pub fn json_result(result: Option<Result<u32, CellError>>, show_errors: bool) -> String {
+    match result {
+        None => "null".to_string(),
+        Some(Ok(value)) => value.to_string(),
+        Some(result) => quote(&result_string(result, show_errors)),
+    }
+}
@@ -0,0 +1,236 @@
+use crate::cell::{Category, CellError, StaticCell};
+use crate::coordinates::Coordinates;
+use crate::parser::VALUE_MAX;
+use crate::rectangle::Rectangle;
+use std::collections::HashMap;
+
+/// The last row and the last column a cell can be stored in
+pub const COORDINATE_MAX: u32 = u32::MAX - 1;
+
+/// Side of the square blocks of the grid
+const SIDE: u32 = 64;
+
+/// A square block of the grid, stored column by column
+/// values : the value of every static cell of the block, a single byte per cell
+/// present : one bit per cell, set when the cell holds something
+/// others : one bit per cell, set when the cell is not a static one
+#[derive(Debug, Clone)]
+struct Block {
+    values: Vec<u8>,
+    present: [u64; SIDE as usize],
+    others: [u64; SIDE as usize],
+}
+
+impl Block {
+
+    fn new() -> Block {
+        Block {
+            values: vec![0; (SIDE * SIDE) as usize],
+            present: [0; SIDE as usize],
+            others: [0; SIDE as usize],
+        }
+    }
+
+    fn is_present(&self, row: u32, col: u32) -> bool {
+        self.present[col as usize] & (1 << row) != 0
+    }
+
+    fn is_other(&self, row: u32, col: u32) -> bool {
+        self.others[col as usize] & (1 << row) != 0
+    }
+
+    fn set_other(&mut self, row: u32, col: u32, other: bool) {
+        self.present[col as usize] |= 1 << row;
+        if other {
+            self.others[col as usize] |= 1 << row;
+        } else {
+            self.others[col as usize] &= !(1 << row);
+        }
+    }
+
+    fn remove(&mut self, row: u32, col: u32) {
+        self.present[col as usize] &= !(1 << row);
+        self.others[col as usize] &= !(1 << row);
+    }
+}
+
+/// Storage of the cells of the spreadsheet, in square blocks found from their position
+/// Only the blocks holding a cell are allocated, so the sheet can grow anywhere
+/// A static cell only takes a byte of its block
+/// Any other cell, formula or faulty, keeps its category aside, found from its coordinates
+/// A cell never written is empty
+#[derive(Default, Debug, Clone)]
+pub struct Grid {
+    blocks: HashMap<(u32, u32), Block>,
+    others: HashMap<Coordinates, Category>,
+    row_count: u32,
+    col_count: u32,
+}
+
+impl Grid {
+
+    pub fn new() -> Grid {
+        Grid {
+            blocks: HashMap::new(),
+            others: HashMap::new(),
+            row_count: 0,
+            col_count: 0,
+        }
+    }
+
+    /// Number of rows up to the last one holding a cell
+    pub fn row_count(&self) -> u32 {
+        self.row_count
+    }
+
+    /// Number of columns up to the last one holding a cell
+    pub fn col_count(&self) -> u32 {
+        self.col_count
+    }
+
+    /// The block holding the cell, and the position of the cell inside it
+    fn locate(coordinates: &Coordinates) -> ((u32, u32), u32, u32) {
+        let block = (coordinates.row / SIDE, coordinates.col / SIDE);
+        (block, coordinates.row % SIDE, coordinates.col % SIDE)
+    }
+
+    /// Return true if a cell was stored at these coordinates
+    pub fn contains(&self, coordinates: &Coordinates) -> bool {
+        let (block, row, col) = Grid::locate(coordinates);
+        self.blocks.get(&block).is_some_and(|block| block.is_present(row, col))
+    }
+
+    /// Make a cell empty again, the sheet keeps its size
+    pub fn remove(&mut self, coordinates: &Coordinates) {
+        let (block, row, col) = Grid::locate(coordinates);
+        if let Some(block) = self.blocks.get_mut(&block) {
+            block.remove(row, col);
+        }
+        self.others.remove(coordinates);
+    }
+
+    /// Store a category at these coordinates, replacing the previous one
+    pub fn set_category(&mut self, coordinates: Coordinates, category: Category) {
+        let (block, row, col) = Grid::locate(&coordinates);
+        let block = self.blocks.entry(block).or_insert_with(Block::new);
+        self.row_count = self.row_count.max(coordinates.row + 1);
+        self.col_count = self.col_count.max(coordinates.col + 1);
+
+        match category {
+            Category::StaticCell(cell) if cell.value <= VALUE_MAX => {
+                block.values[(col * SIDE + row) as usize] = cell.value as u8;
+                block.set_other(row, col, false);
+                self.others.remove(&coordinates);
+            }
+            category => {
+                block.set_other(row, col, true);
+                self.others.insert(coordinates, category);
+            }
+        }
+    }
+
+    /// The category of a cell which is not a static one, None for a static or an empty cell
+    pub fn get_other(&self, coordinates: &Coordinates) -> Option<&Category> {
+        self.others.get(coordinates)
+    }
+
+    /// The category of a cell, built again for a static cell, None for an empty cell
+    pub fn get_category(&self, coordinates: &Coordinates) -> Option<Category> {
+        match self.get_other(coordinates) {
+            Some(category) => Some(category.clone()),
+            None => self.get_special(coordinates)
+                .map(|value| Category::StaticCell(StaticCell::from(value))),
+        }
+    }
+
+    /// Return the value of a cell, or the reason why it has none
+    /// None for an empty cell, which is not faulty but has no value either
+    pub fn get_result(&self, coordinates: &Coordinates) -> Option<Result<u32, CellError>> {
+        let (block, row, col) = Grid::locate(coordinates);
+        match self.blocks.get(&block) {
+            Some(block) if block.is_present(row, col) => Some(self.block_result(block, coordinates)),
+            _ => None,
+        }
+    }
+
+    fn block_result(&self, block: &Block, coordinates: &Coordinates) -> Result<u32, CellError> {
+        let (_, row, col) = Grid::locate(coordinates);
+        if block.is_other(row, col) {
+            self.others[coordinates].get_result()
+        } else {
+            Ok(block.values[(col * SIDE + row) as usize] as u32)
+        }
+    }
+
+    /// Return the value of a cell, None for a faulty or an empty cell
+    pub fn get_special(&self, coordinates: &Coordinates) -> Option<u32> {
+        self.get_result(coordinates).and_then(Result::ok)
+    }
+
+    /// Return the reason why the cell has no value, None if it has one or if it is empty
+    pub fn get_error(&self, coordinates: &Coordinates) -> Option<CellError> {
+        self.get_result(coordinates).and_then(Result::err)
+    }
+
+    /// Results of every cell of an area which is not empty, in no particular order
+    /// Only the blocks holding a cell are browsed, the area can be as large as wanted
+    pub fn results(&self, rectangle: &Rectangle) -> Vec<Result<u32, CellError>> {
+        let mut results = Vec::new();
+        self.browse(rectangle, |block, coordinates| results.push(self.block_result(block, &coordinates)));
+        results
+    }
+
+    /// Coordinates of every cell of an area which is not empty, in no particular order
+    /// Only the blocks holding a cell are browsed, the area can be as large as wanted
+    pub fn cells(&self, rectangle: &Rectangle) -> Vec<Coordinates> {
+        let mut cells = Vec::new();
+        self.browse(rectangle, |_, coordinates| cells.push(coordinates));
+        cells
+    }
+
+    /// Call visit on every cell of an area which is not empty, with the block holding it
+    fn browse<'a, F: FnMut(&'a Block, Coordinates)>(&'a self, rectangle: &Rectangle, mut visit: F) {
+        if rectangle.begin.row >= self.row_count || rectangle.begin.col >= self.col_count {
+            return;
+        }
+        let area = Rectangle::from(rectangle.begin, Coordinates::from(
+            rectangle.end.row.min(self.row_count - 1),
+            rectangle.end.col.min(self.col_count - 1),
+        ));
+
+        // Look for the blocks of the area one by one only if there are less of them than blocks
+        let (top, bottom) = (area.begin.row / SIDE, area.end.row / SIDE);
+        let (left, right) = (area.begin.col / SIDE, area.end.col / SIDE);
+        let spanned = (bottom - top + 1) as u64 * (right - left + 1) as u64;
+        if spanned <= self.blocks.len() as u64 {
+            for block_col in left..=right {
+                for block_row in top..=bottom {
+                    if let Some(block) = self.blocks.get(&(block_row, block_col)) {
+                        Grid::browse_block(block, (block_row, block_col), &area, &mut visit);
+                    }
+                }
+            }
+        } else {
+            for (&position, block) in &self.blocks {
+                if (top..=bottom).contains(&position.0) && (left..=right).contains(&position.1) {
+                    Grid::browse_block(block, position, &area, &mut visit);
+                }
+            }
+        }
+    }
+
+    /// Call visit on every cell of a block inside an area which is not empty
+    fn browse_block<'a, F: FnMut(&'a Block, Coordinates)>(block: &'a Block, position: (u32, u32), area: &Rectangle,
+                                                          visit: &mut F) {
+        let (first_row, first_col) = (position.0 * SIDE, position.1 * SIDE);
+        let rows = area.begin.row.max(first_row)..=area.end.row.min(first_row + SIDE - 1);
+        let cols = area.begin.col.max(first_col)..=area.end.col.min(first_col + SIDE - 1);
+        for col in cols {
+            for row in rows.clone() {
+                if block.is_present(row - first_row, col - first_col) {
+                    visit(block, Coordinates::from(row, col));
+                }
+            }
+        }
+    }
+}
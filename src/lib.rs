@@ -0,0 +1,39 @@
+//! Spreadsheet engine: cells holding values or formulas, evaluated and kept up to date
+//! while the user changes them.
+//!
+//! A sheet is read from any reader, evaluated, then changed one cell at a time.
+//! Every change gives back the cells whose result changed.
+//!
+//! ```
+//! use defi2::{Coordinates, SpreadSheet};
+//!
+//! let mut sheet = SpreadSheet::new();
+//! sheet.read_data("1;2\n=#(0,0,0,1,>0);=SUM(A1:B1)\n".as_bytes()).unwrap();
+//! sheet.evaluate();
+//! assert_eq!(sheet.get_result(&Coordinates::from(1, 1)), Some(Ok(3)));
+//!
+//! sheet.apply_line("0 0 5").unwrap();
+//! let changes = sheet.take_changes();
+//! assert_eq!(changes[&Coordinates::from(1, 1)], Some(Ok(7)));
+//! ```
+
+mod cell;
+mod spreadsheet;
+mod rectangle;
+mod coordinates;
+pub mod check;
+mod parser;
+mod dependencies;
+mod occurrences;
+mod grid;
+mod error;
+mod format;
+mod repl;
+
+pub use cell::{CellError, Propagation};
+pub use coordinates::{Coordinates, Notation};
+pub use error::Error;
+pub use format::Format;
+pub use parser::{parse_value, ParseError};
+pub use repl::Repl;
+pub use spreadsheet::SpreadSheet;
@@ -1,15 +1,419 @@
-#[macro_use] extern crate lazy_static;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use defi2::{check, parse_value, Coordinates, Error, Format, Notation, Propagation, Repl, SpreadSheet};
 use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::process;
 
-pub mod cell;
-pub mod spreadsheet;
-pub mod rectangle;
-pub mod coordinates;
-pub mod check;
-pub mod rules;
+/// Spreadsheet engine: evaluate a sheet, then keep it up to date while the user changes it
+#[derive(Debug, Parser)]
+#[command(name = "ws", version)]
+struct Cli {
+    #[command(subcommand)]
+    action: Action,
+}
+
+/// Every path can be "-", to read from the standard input or write to the standard output
+#[derive(Debug, Subcommand)]
+enum Action {
+    /// Evaluate the data file, write its view, then apply the user file and write its changes
+    Eval(EvalArgs),
+    /// Check that the data, user or view files are well formed
+    Check(CheckArgs),
+    /// Apply the user file on the data file, and write the view of the sheet once changed
+    Apply(ApplyArgs),
+    /// Explain why a cell has its result
+    Explain(ExplainArgs),
+    /// Write the dependency graph, as DOT or JSON
+    Graph(GraphArgs),
+    /// Load the data file, then read changes, get, show and undo commands from the standard input
+    Repl(ReplArgs),
+}
+
+/// Options shared by the subcommands evaluating a sheet
+#[derive(Debug, Args)]
+struct SheetOptions {
+    /// Print the kind of error of faulty cells instead of "P"
+    #[arg(long)]
+    errors: bool,
+    /// How coordinates are written: numeric, a1 or r1c1
+    #[arg(long, default_value = "numeric", value_parser = parse_notation)]
+    notation: Notation,
+    /// How a formula reading a faulty cell is evaluated: propagate or skip
+    #[arg(long, default_value = "propagate", value_parser = parse_propagation)]
+    propagation: Propagation,
+    /// Count occurrences from an index of the values instead of browsing the cells
+    #[arg(long)]
+    occurrence_index: bool,
+    /// Value of the cells missing at the end of a shorter row, or "empty"
+    #[arg(long, default_value = "empty", value_parser = parse_padding)]
+    padding: Padding,
+    /// Number of threads evaluating the spreadsheet, one per core by default
+    #[arg(long)]
+    threads: Option<usize>,
+    /// How the outputs are written: text or json
+    #[arg(long, default_value = "text", value_parser = parse_format)]
+    format: Format,
+}
+
+/// Options of the subcommands applying a user file
+#[derive(Debug, Args)]
+struct InvalidOptions {
+    /// What to do with a line of the user file which can't be applied: abort, skip or record
+    #[arg(long, default_value = "abort", value_parser = parse_invalid)]
+    invalid: Invalid,
+    /// File where the invalid lines are reported, instead of the error output
+    #[arg(long, value_name = "PATH")]
+    diagnostics: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct EvalArgs {
+    /// Data file to evaluate
+    #[arg(long, value_name = "PATH")]
+    data: String,
+    /// User file, one change per line
+    #[arg(long, value_name = "PATH")]
+    user: String,
+    /// Where the view of the evaluated data is written
+    #[arg(long, value_name = "PATH", default_value = "-")]
+    view: String,
+    /// Where the changes of every user line are written
+    #[arg(long, value_name = "PATH", default_value = "-")]
+    changes: String,
+    #[command(flatten)]
+    invalid: InvalidOptions,
+    #[command(flatten)]
+    sheet: SheetOptions,
+}
+
+#[derive(Debug, Args)]
+#[command(group = clap::ArgGroup::new("inputs").required(true).multiple(true))]
+struct CheckArgs {
+    /// Data file to check
+    #[arg(long, value_name = "PATH", group = "inputs")]
+    data: Option<String>,
+    /// User file to check
+    #[arg(long, value_name = "PATH", group = "inputs")]
+    user: Option<String>,
+    /// View file to check
+    #[arg(long, value_name = "PATH", group = "inputs")]
+    view: Option<String>,
+    /// Where the problems are reported
+    #[arg(long, value_name = "PATH", default_value = "-")]
+    output: String,
+    /// How the problems are written: text or json
+    #[arg(long, default_value = "text", value_parser = parse_format)]
+    format: Format,
+}
+
+#[derive(Debug, Args)]
+struct ApplyArgs {
+    /// Data file holding the sheet
+    #[arg(long, value_name = "PATH")]
+    data: String,
+    /// User file, one change per line
+    #[arg(long, value_name = "PATH")]
+    user: String,
+    /// Where the view of the changed sheet is written
+    #[arg(long, value_name = "PATH", default_value = "-")]
+    output: String,
+    #[command(flatten)]
+    invalid: InvalidOptions,
+    #[command(flatten)]
+    sheet: SheetOptions,
+}
+
+#[derive(Debug, Args)]
+struct ExplainArgs {
+    /// Data file holding the sheet
+    #[arg(long, value_name = "PATH")]
+    data: String,
+    /// User file applied before explaining the cell
+    #[arg(long, value_name = "PATH")]
+    user: Option<String>,
+    /// Cell to explain, as "r,c", A1 or R1C1
+    #[arg(long, value_parser = parse_cell)]
+    cell: Coordinates,
+    /// Where the explanation is written
+    #[arg(long, value_name = "PATH", default_value = "-")]
+    output: String,
+    #[command(flatten)]
+    sheet: SheetOptions,
+}
+
+#[derive(Debug, Args)]
+struct GraphArgs {
+    /// Data file holding the sheet
+    #[arg(long, value_name = "PATH")]
+    data: String,
+    /// User file applied before writing the graph
+    #[arg(long, value_name = "PATH")]
+    user: Option<String>,
+    /// Where the graph is written
+    #[arg(long, value_name = "PATH", default_value = "-")]
+    output: String,
+    #[command(flatten)]
+    sheet: SheetOptions,
+}
+
+#[derive(Debug, Args)]
+struct ReplArgs {
+    /// Data file holding the sheet
+    #[arg(value_name = "PATH")]
+    data: String,
+    #[command(flatten)]
+    sheet: SheetOptions,
+}
+
+/// Value of the padding option
+#[derive(Debug, Clone, Copy)]
+struct Padding(Option<u32>);
+
+/// What to do with a line of the user file which can't be applied
+/// Abort : stop with the error
+/// Skip : report the line, and leave it out of the changes file
+/// Record : report the line, and keep it in the changes file with no change after it
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Invalid {
+    Abort,
+    Skip,
+    Record,
+}
+
+impl Invalid {
+
+    fn from_name(name: &str) -> Option<Invalid> {
+        match name.to_ascii_lowercase().as_str() {
+            "abort" => Some(Invalid::Abort),
+            "skip" => Some(Invalid::Skip),
+            "record" => Some(Invalid::Record),
+            _ => None,
+        }
+    }
+}
+
+fn parse_notation(name: &str) -> Result<Notation, String> {
+    Notation::from_name(name).ok_or_else(|| "expected numeric, a1 or r1c1".to_string())
+}
+
+fn parse_propagation(name: &str) -> Result<Propagation, String> {
+    Propagation::from_name(name).ok_or_else(|| "expected propagate or skip".to_string())
+}
+
+fn parse_format(name: &str) -> Result<Format, String> {
+    Format::from_name(name).ok_or_else(|| "expected text or json".to_string())
+}
+
+fn parse_invalid(name: &str) -> Result<Invalid, String> {
+    Invalid::from_name(name).ok_or_else(|| "expected abort, skip or record".to_string())
+}
+
+fn parse_padding(value: &str) -> Result<Padding, String> {
+    if value == "empty" {
+        return Ok(Padding(None));
+    }
+    parse_value(value)
+        .map(|value| Padding(Some(value)))
+        .map_err(|_| "expected a value between 0 and 255 or empty".to_string())
+}
+
+/// A cell is written "r,c" from 0, or with the A1 or R1C1 notation
+fn parse_cell(cell: &str) -> Result<Coordinates, String> {
+    let numeric = cell.split_once(',')
+        .and_then(|(row, col)| Some(Coordinates::from(row.trim().parse().ok()?, col.trim().parse().ok()?)));
+    numeric.or_else(|| Coordinates::parse(&cell.to_ascii_uppercase()))
+        .ok_or_else(|| "expected r,c, A1 or R1C1".to_string())
+}
 
+/// Open a file to read, or the standard input for "-"
+fn open_input(path: &str) -> Result<Box<dyn BufRead>, Error> {
+    if path == "-" {
+        return Ok(Box::new(BufReader::new(io::stdin())));
+    }
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+/// Create a file to write, or the standard output for "-"
+fn create_output(path: &str) -> Result<Box<dyn Write>, Error> {
+    if path == "-" {
+        return Ok(Box::new(io::stdout()));
+    }
+    Ok(Box::new(BufWriter::new(File::create(path)?)))
+}
+
+/// The standard input can only be read once
+fn check_inputs(paths: &[Option<&String>]) -> Result<(), Error> {
+    if paths.iter().filter(|path| path.is_some_and(|path| path == "-")).count() > 1 {
+        return Err(Error::Usage("Only one input can be read from the standard input".to_string()));
+    }
+    Ok(())
+}
+
+/// Read the data file and evaluate it with the options
+fn load(path: &str, options: &SheetOptions) -> Result<SpreadSheet, Error> {
+    if let Some(threads) = options.threads {
+        // The pool can only be set up once, a second setup is ignored
+        let _ = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
+    }
+
+    let mut sheet = SpreadSheet::new();
+    sheet.set_show_errors(options.errors);
+    sheet.set_notation(options.notation);
+    sheet.set_propagation(options.propagation);
+    sheet.set_indexed(options.occurrence_index);
+    sheet.set_padding(options.padding.0);
+    sheet.set_format(options.format);
+
+    sheet.read_data(open_input(path)?)?;
+    sheet.evaluate();
+    Ok(sheet)
+}
+
+/// Apply the user file on the sheet, and write the changes of each line if there is somewhere to write them
+/// A line which can't be applied is reported with its number and the reason, unless the run stops
+fn apply_user(sheet: &mut SpreadSheet, path: &str, options: &InvalidOptions,
+              mut changes_file: Option<&mut dyn Write>) -> Result<(), Error> {
+    let mut diagnostics: Box<dyn Write> = match &options.diagnostics {
+        Some(path) => create_output(path)?,
+        None => Box::new(io::stderr()),
+    };
+
+    for (number, line) in open_input(path)?.lines().enumerate() {
+        let line = line?;
+        if let Err(error) = sheet.apply_line(&line) {
+            let error = error.at_line(number + 1).in_file(path);
+            match options.invalid {
+                Invalid::Abort => return Err(error),
+                Invalid::Skip => {
+                    writeln!(diagnostics, "{}", error)?;
+                    continue;
+                }
+                Invalid::Record => writeln!(diagnostics, "{}", error)?,
+            }
+        }
+        let changes = sheet.take_changes();
+        if let Some(changes_file) = &mut changes_file {
+            sheet.write_changes(&line, &changes, changes_file)?;
+        }
+    }
+
+    Ok(diagnostics.flush()?)
+}
+
+/// Read the data file, write its view, then apply the user file and write its changes
+fn eval(args: &EvalArgs) -> Result<(), Error> {
+    check_inputs(&[Some(&args.data), Some(&args.user)])?;
+
+    let mut sheet = load(&args.data, &args.sheet)?;
+    let mut view = create_output(&args.view)?;
+    sheet.write_view(&mut view)?;
+    view.flush()?;
+
+    let mut changes = create_output(&args.changes)?;
+    apply_user(&mut sheet, &args.user, &args.invalid, Some(&mut changes))?;
+    Ok(changes.flush()?)
+}
+
+/// A check of a file, giving back every problem found
+type Check = fn(Box<dyn BufRead>) -> Result<Vec<Error>, Error>;
+
+/// Report every problem of the given files, return false if there is one
+fn check(args: &CheckArgs) -> Result<bool, Error> {
+    check_inputs(&[args.data.as_ref(), args.user.as_ref(), args.view.as_ref()])?;
+
+    let mut problems = Vec::new();
+    let checks: [(&Option<String>, Check); 3] = [
+        (&args.data, check::check_data),
+        (&args.user, check::check_user),
+        (&args.view, check::check_view),
+    ];
+    for (path, check) in checks {
+        if let Some(path) = path {
+            problems.extend(check(open_input(path)?)?.into_iter().map(|error| error.in_file(path)));
+        }
+    }
+
+    let mut output = create_output(&args.output)?;
+    for problem in &problems {
+        writeln!(output, "{}", args.format.problem_string(problem))?;
+    }
+    output.flush()?;
+    Ok(problems.is_empty())
+}
+
+/// Apply the user file on the data file, and write the view of the changed sheet
+fn apply(args: &ApplyArgs) -> Result<(), Error> {
+    check_inputs(&[Some(&args.data), Some(&args.user)])?;
+
+    let mut sheet = load(&args.data, &args.sheet)?;
+    apply_user(&mut sheet, &args.user, &args.invalid, None)?;
+    sheet.write_view(create_output(&args.output)?)
+}
+
+/// Explain why a cell has its result, once the user file is applied
+fn explain(args: &ExplainArgs) -> Result<(), Error> {
+    check_inputs(&[Some(&args.data), args.user.as_ref()])?;
+
+    let mut sheet = load(&args.data, &args.sheet)?;
+    if let Some(user) = &args.user {
+        apply_user(&mut sheet, user, &InvalidOptions { invalid: Invalid::Abort, diagnostics: None }, None)?;
+    }
+    sheet.write_explanation(&args.cell, create_output(&args.output)?)
+}
+
+/// Write the dependency graph, once the user file is applied
+fn graph(args: &GraphArgs) -> Result<(), Error> {
+    check_inputs(&[Some(&args.data), args.user.as_ref()])?;
+
+    let mut sheet = load(&args.data, &args.sheet)?;
+    if let Some(user) = &args.user {
+        apply_user(&mut sheet, user, &InvalidOptions { invalid: Invalid::Abort, diagnostics: None }, None)?;
+    }
+    sheet.write_graph(create_output(&args.output)?)
+}
+
+/// Edit the sheet from the commands of the standard input, and write every output at once
+/// A prompt is only written for someone typing the commands
+fn repl(args: &ReplArgs) -> Result<(), Error> {
+    if args.data == "-" {
+        return Err(Error::Usage("The commands are read from the standard input, not the data file".to_string()));
+    }
+
+    let mut repl = Repl::new(load(&args.data, &args.sheet)?);
+    let prompt = if io::stdin().is_terminal() { "> " } else { "" };
+    repl.run(io::stdin().lock(), io::stdout(), prompt)
+}
+
+/// Turn the command line of the specification, `ws data.csv user.txt view0.csv changes.txt [options]`,
+/// into the eval subcommand, any other command line is left as it is
+fn legacy_args(mut args: Vec<String>) -> Vec<String> {
+    let is_subcommand = |name: &str| name == "help" || Cli::command().find_subcommand(name).is_some();
+    if args.len() < 5 || args[1..5].iter().any(|arg| arg.starts_with('-')) || is_subcommand(&args[1]) {
+        return args;
+    }
+    let options = args.split_off(5);
+    let mut eval = vec![args[0].clone(), "eval".to_string()];
+    for (flag, path) in ["--data", "--user", "--view", "--changes"].iter().zip(&args[1..]) {
+        eval.push(flag.to_string());
+        eval.push(path.clone());
+    }
+    eval.extend(options);
+    eval
+}
 
 pub fn main() {
-    let args: Vec<String> = env::args().collect();
-    spreadsheet::SpreadSheet::process(&args).unwrap();
+    let cli = Cli::parse_from(legacy_args(env::args().collect()));
+    let result = match &cli.action {
+        Action::Eval(args) => eval(args),
+        Action::Check(args) => check(args).map(|valid| if !valid { process::exit(1) }),
+        Action::Apply(args) => apply(args),
+        Action::Explain(args) => explain(args),
+        Action::Graph(args) => graph(args),
+        Action::Repl(args) => repl(args),
+    };
+    if let Err(error) = result {
+        eprintln!("ERROR - {}", error);
+        process::exit(1);
+    }
 }
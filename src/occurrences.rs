@@ -0,0 +1,149 @@
+use crate::coordinates::Coordinates;
+use crate::parser::{Predicate, VALUE_MAX};
+use crate::rectangle::Rectangle;
+
+/// Slot of the faulty cells, after the slots of the values 0..=255
+const FAULTY: usize = VALUE_MAX as usize + 1;
+
+/// Biggest spreadsheet the index is built for, in cells
+/// A tree takes 4 bytes per cell of the spreadsheet and there are up to 257 trees,
+/// so the index stays under about 1 GiB
+pub const CELLS_MAX: usize = 1 << 20;
+
+/// A 2D Fenwick tree counting cells over the spreadsheet grid
+/// Adding a cell and counting the cells of a rectangle both take a logarithmic time
+#[derive(Debug, Clone)]
+struct Fenwick {
+    rows: usize,
+    cols: usize,
+    tree: Vec<i32>,
+}
+
+impl Fenwick {
+
+    fn new(rows: usize, cols: usize) -> Fenwick {
+        Fenwick {
+            rows,
+            cols,
+            tree: vec![0; (rows + 1) * (cols + 1)],
+        }
+    }
+
+    fn add(&mut self, coordinates: &Coordinates, delta: i32) {
+        let mut row = coordinates.row as usize + 1;
+        while row <= self.rows {
+            let mut col = coordinates.col as usize + 1;
+            while col <= self.cols {
+                self.tree[row * (self.cols + 1) + col] += delta;
+                col += col & col.wrapping_neg();
+            }
+            row += row & row.wrapping_neg();
+        }
+    }
+
+    /// Number of cells counted in the rows before `rows` and the columns before `cols`
+    fn prefix(&self, rows: usize, cols: usize) -> i32 {
+        let mut count = 0;
+        let mut row = rows;
+        while row > 0 {
+            let mut col = cols;
+            while col > 0 {
+                count += self.tree[row * (self.cols + 1) + col];
+                col -= col & col.wrapping_neg();
+            }
+            row -= row & row.wrapping_neg();
+        }
+        count
+    }
+
+    /// Number of cells counted in the rectangle, the part of it beyond the tree is left out
+    fn count(&self, rectangle: &Rectangle) -> u32 {
+        let (top, left) = (rectangle.begin.row as usize, rectangle.begin.col as usize);
+        if top >= self.rows || left >= self.cols {
+            return 0;
+        }
+        let bottom = (rectangle.end.row as usize + 1).min(self.rows);
+        let right = (rectangle.end.col as usize + 1).min(self.cols);
+        let count = self.prefix(bottom, right) - self.prefix(top, right)
+            - self.prefix(bottom, left) + self.prefix(top, left);
+        count as u32
+    }
+}
+
+/// Index of the values of the spreadsheet, to count occurrences without browsing the cells
+/// There is one Fenwick tree per value, and one for the faulty cells
+/// A tree is only built once a cell holds its value
+/// The trees are as big as the spreadsheet, which is limited to CELLS_MAX cells
+/// The trees cover the sheet the index was built with, a cell beyond is not counted
+#[derive(Debug, Clone)]
+pub struct OccurrenceIndex {
+    rows: usize,
+    cols: usize,
+    values: Vec<Option<Fenwick>>,
+}
+
+impl OccurrenceIndex {
+
+    /// Return None if the spreadsheet has more than CELLS_MAX cells
+    pub fn new(row_max: u32, col_max: u32) -> Option<OccurrenceIndex> {
+        let (rows, cols) = (row_max as usize + 1, col_max as usize + 1);
+        if rows.checked_mul(cols).is_none_or(|cells| cells > CELLS_MAX) {
+            return None;
+        }
+        Some(OccurrenceIndex {
+            rows,
+            cols,
+            values: vec![None; FAULTY + 1],
+        })
+    }
+
+    fn slot(value: Option<u32>) -> usize {
+        value.map_or(FAULTY, |value| value as usize)
+    }
+
+    /// Count a cell holding a value, None for a faulty cell
+    pub fn add(&mut self, coordinates: &Coordinates, value: Option<u32>) {
+        let (rows, cols) = (self.rows, self.cols);
+        self.values[OccurrenceIndex::slot(value)]
+            .get_or_insert_with(|| Fenwick::new(rows, cols))
+            .add(coordinates, 1);
+    }
+
+    /// Stop counting a cell, its value must be the one it was counted with
+    pub fn remove(&mut self, coordinates: &Coordinates, value: Option<u32>) {
+        if let Some(tree) = &mut self.values[OccurrenceIndex::slot(value)] {
+            tree.add(coordinates, -1);
+        }
+    }
+
+    /// Number of cells of the rectangle whose value matches the predicate
+    pub fn count(&self, rectangle: &Rectangle, predicate: &Predicate) -> u32 {
+        (0..=VALUE_MAX)
+            .filter(|&value| predicate.matches(Some(value)))
+            .filter_map(|value| self.values[value as usize].as_ref())
+            .map(|tree| tree.count(rectangle))
+            .sum()
+    }
+
+    /// Return true if a cell of the rectangle is faulty
+    pub fn has_faulty(&self, rectangle: &Rectangle) -> bool {
+        self.values[FAULTY].as_ref().is_some_and(|tree| tree.count(rectangle) > 0)
+    }
+
+    /// The parts of a rectangle beyond the cells covered by the index
+    /// The rows below the index come first, then the columns on its right
+    pub fn outside(&self, rectangle: &Rectangle) -> Vec<Rectangle> {
+        let (rows, cols) = (self.rows as u32, self.cols as u32);
+        let mut parts = Vec::new();
+        if rectangle.end.row >= rows {
+            let begin = Coordinates::from(rectangle.begin.row.max(rows), rectangle.begin.col);
+            parts.push(Rectangle::from(begin, rectangle.end));
+        }
+        if rectangle.end.col >= cols && rectangle.begin.row < rows {
+            let begin = Coordinates::from(rectangle.begin.row, rectangle.begin.col.max(cols));
+            let end = Coordinates::from(rectangle.end.row.min(rows - 1), rectangle.end.col);
+            parts.push(Rectangle::from(begin, end));
+        }
+        parts
+    }
+}
@@ -0,0 +1,722 @@
+use crate::coordinates::Coordinates;
+use crate::rectangle::Rectangle;
+use std::fmt;
+
+/// Biggest value a cell can hold
+pub const VALUE_MAX: u32 = 255;
+
+/// Deepest expression accepted, in levels of parentheses or of operators
+/// Parsing and evaluating an expression recurse once per level
+pub const DEPTH_MAX: usize = 256;
+
+/// Tokens produced by the tokenizer
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Number(u32),
+    Ident(String),
+    Equal,
+    Hash,
+    LParen,
+    RParen,
+    Comma,
+    Colon,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    NotEqual,
+    LBracket,
+    RBracket,
+    DotDot,
+}
+
+/// Arithmetic operators usable between expressions
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Condition a cell value has to match to be counted by `#(r1, c1, r2, c2, predicate)`
+/// Between is an inclusive interval, written `[a..b]`
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Predicate {
+    Equal(u32),
+    NotEqual(u32),
+    Less(u32),
+    LessEqual(u32),
+    Greater(u32),
+    GreaterEqual(u32),
+    Between(u32, u32),
+}
+
+impl Predicate {
+
+    /// Return true if the value is matching, a faulty cell (None) never match
+    pub fn matches(&self, value: Option<u32>) -> bool {
+        match value {
+            Some(value) => match *self {
+                Predicate::Equal(v) => value == v,
+                Predicate::NotEqual(v) => value != v,
+                Predicate::Less(v) => value < v,
+                Predicate::LessEqual(v) => value <= v,
+                Predicate::Greater(v) => value > v,
+                Predicate::GreaterEqual(v) => value >= v,
+                Predicate::Between(low, high) => low <= value && value <= high,
+            },
+            None => false,
+        }
+    }
+}
+
+/// Aggregate functions computed over a rectangle
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Function {
+    Sum,
+    Min,
+    Max,
+    Average,
+}
+
+impl Function {
+
+    pub fn from_name(name: &str) -> Option<Function> {
+        match name {
+            "SUM" => Some(Function::Sum),
+            "MIN" => Some(Function::Min),
+            "MAX" => Some(Function::Max),
+            "AVERAGE" => Some(Function::Average),
+            _ => None,
+        }
+    }
+}
+
+/// Formula expression tree
+/// Number : an integer constant
+/// Ref : the value of another cell, written `A1` or `R1C1`
+/// Occur : `#(r1, c1, r2, c2, v)`, the number of cells matching the predicate inside the rectangle
+/// Aggregate : `SUM(r1, c1, r2, c2)`, a function computed over the rectangle
+/// Binary : an arithmetic operation between two expressions
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Expr {
+    Number(u32),
+    Ref(Coordinates),
+    Occur(Rectangle, Predicate),
+    Aggregate(Function, Rectangle),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+
+    /// Every area read by the expression, a reference being an area of one cell
+    pub fn areas(&self, areas: &mut Vec<Rectangle>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Ref(coordinates) => areas.push(Rectangle::from(*coordinates, *coordinates)),
+            Expr::Occur(rectangle, _) => areas.push(*rectangle),
+            Expr::Aggregate(_, rectangle) => areas.push(*rectangle),
+            Expr::Binary(_, left, right) => {
+                left.areas(areas);
+                right.areas(areas);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Sub => write!(f, "-"),
+            Operator::Mul => write!(f, "*"),
+            Operator::Div => write!(f, "/"),
+        }
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Predicate::Equal(v) => write!(f, "{}", v),
+            Predicate::NotEqual(v) => write!(f, "!={}", v),
+            Predicate::Less(v) => write!(f, "<{}", v),
+            Predicate::LessEqual(v) => write!(f, "<={}", v),
+            Predicate::Greater(v) => write!(f, ">{}", v),
+            Predicate::GreaterEqual(v) => write!(f, ">={}", v),
+            Predicate::Between(low, high) => write!(f, "[{}..{}]", low, high),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Function::Sum => write!(f, "SUM"),
+            Function::Min => write!(f, "MIN"),
+            Function::Max => write!(f, "MAX"),
+            Function::Average => write!(f, "AVERAGE"),
+        }
+    }
+}
+
+/// Write a rectangle the way a formula reads it, `r1,c1,r2,c2`
+fn write_rectangle(f: &mut fmt::Formatter, rectangle: &Rectangle) -> fmt::Result {
+    write!(f, "{},{},{},{}", rectangle.begin.row, rectangle.begin.col, rectangle.end.row, rectangle.end.col)
+}
+
+/// Write an expression the way it can be parsed again
+/// Parentheses are only written where the precedence of the operators needs them
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Number(number) => write!(f, "{}", number),
+            Expr::Ref(coordinates) => write!(f, "{}", coordinates.to_a1()),
+            Expr::Occur(rectangle, predicate) => {
+                write!(f, "#(")?;
+                write_rectangle(f, rectangle)?;
+                write!(f, ",{})", predicate)
+            }
+            Expr::Aggregate(function, rectangle) => {
+                write!(f, "{}(", function)?;
+                write_rectangle(f, rectangle)?;
+                write!(f, ")")
+            }
+            Expr::Binary(operator, left, right) => {
+                let multiplicative = |operator: &Operator| matches!(operator, Operator::Mul | Operator::Div);
+                // The left operand only needs them if it binds less, the operators being left associative
+                let left_parens = match **left {
+                    Expr::Binary(ref inner, _, _) => multiplicative(operator) && !multiplicative(inner),
+                    _ => false,
+                };
+                let right_parens = match **right {
+                    Expr::Binary(ref inner, _, _) => multiplicative(operator) || !multiplicative(inner),
+                    _ => false,
+                };
+                if left_parens {
+                    write!(f, "({})", left)?;
+                } else {
+                    write!(f, "{}", left)?;
+                }
+                write!(f, "{}", operator)?;
+                if right_parens {
+                    write!(f, "({})", right)
+                } else {
+                    write!(f, "{}", right)
+                }
+            }
+        }
+    }
+}
+
+/// What a cell contains once parsed
+/// Value : a plain integer between 0 and 255
+/// Formula : an expression starting with `=`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Datum {
+    Value(u32),
+    Formula(Expr),
+}
+
+/// Write the datum the way it is written in the data file
+impl fmt::Display for Datum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Datum::Value(value) => write!(f, "{}", value),
+            Datum::Formula(expr) => write!(f, "={}", expr),
+        }
+    }
+}
+
+/// A parsed line of the user file : `r c d`, or `A1 d`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub coordinates: Coordinates,
+    pub datum: Datum,
+}
+
+/// Error raised while tokenizing or parsing
+/// column : position (starting at 1) of the faulty character in the input
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(column: usize, message: &str) -> ParseError {
+        ParseError {
+            column,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Split the input into tokens, whitespaces are ignored everywhere
+/// Every token is stored with its column to report errors
+pub fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        let column = index + 1;
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '=' => Token::Equal,
+            '#' => Token::Hash,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '<' | '>' | '!' | '.' => {
+                let equal = chars.peek().map(|&(_, next)| next == '=').unwrap_or(false);
+                let dot = chars.peek().map(|&(_, next)| next == '.').unwrap_or(false);
+                let token = match c {
+                    '<' if equal => Token::LessEqual,
+                    '<' => Token::Less,
+                    '>' if equal => Token::GreaterEqual,
+                    '>' => Token::Greater,
+                    '!' if equal => Token::NotEqual,
+                    '.' if dot => Token::DotDot,
+                    _ => return Err(ParseError::new(column, &format!("unexpected character '{}'", c))),
+                };
+                if equal || dot {
+                    chars.next();
+                }
+                token
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut ident = c.to_ascii_uppercase().to_string();
+                while let Some(&(_, next)) = chars.peek() {
+                    if !next.is_ascii_alphanumeric() {
+                        break;
+                    }
+                    ident.push(next.to_ascii_uppercase());
+                    chars.next();
+                }
+                Token::Ident(ident)
+            }
+            '0'..='9' => {
+                let mut number = c.to_digit(10).unwrap();
+                while let Some(&(_, next)) = chars.peek() {
+                    match next.to_digit(10) {
+                        Some(digit) => {
+                            number = number.checked_mul(10)
+                                .and_then(|n| n.checked_add(digit))
+                                .ok_or_else(|| ParseError::new(column, "number too large"))?;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                Token::Number(number)
+            }
+            _ => return Err(ParseError::new(column, &format!("unexpected character '{}'", c))),
+        };
+        tokens.push((token, column));
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over a list of tokens
+pub struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    end: usize,
+    nesting: usize,
+}
+
+/// An expression with the depth of its tree
+type Tree = (Expr, usize);
+
+/// Error of an expression deeper than DEPTH_MAX
+fn too_deep(column: usize) -> ParseError {
+    ParseError::new(column, "expression nested too deeply")
+}
+
+impl Parser {
+
+    pub fn new(input: &str) -> Result<Parser, ParseError> {
+        Ok(Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+            end: input.len() + 1,
+            nesting: 0,
+        })
+    }
+
+    /// Column of the current token, or the end of the input
+    fn column(&self) -> usize {
+        match self.tokens.get(self.position) {
+            Some((_, column)) => *column,
+            None => self.end,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn peek_second(&self) -> Option<&Token> {
+        self.tokens.get(self.position + 1).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        let column = self.column();
+        match self.next() {
+            Some(ref token) if *token == expected => Ok(()),
+            Some(token) => Err(ParseError::new(column, &format!("expected {:?}, found {:?}", expected, token))),
+            None => Err(ParseError::new(column, &format!("expected {:?}, found end of input", expected))),
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, ParseError> {
+        let column = self.column();
+        match self.next() {
+            Some(Token::Number(number)) => Ok(number),
+            Some(token) => Err(ParseError::new(column, &format!("expected a number, found {:?}", token))),
+            None => Err(ParseError::new(column, "expected a number, found end of input")),
+        }
+    }
+
+    /// A number between 0 and 255
+    fn value(&mut self) -> Result<u32, ParseError> {
+        let column = self.column();
+        let value = self.number()?;
+        if value > VALUE_MAX {
+            return Err(ParseError::new(column, "value must be between 0 and 255"));
+        }
+        Ok(value)
+    }
+
+    fn end_of_input(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(ParseError::new(self.column(), &format!("unexpected {:?}", token))),
+        }
+    }
+
+    /// datum := value | '=' expr
+    pub fn datum(&mut self) -> Result<Datum, ParseError> {
+        if let Some(Token::Equal) = self.peek() {
+            self.next();
+            Ok(Datum::Formula(self.expr()?))
+        } else {
+            Ok(Datum::Value(self.value()?))
+        }
+    }
+
+    /// expr := term (('+' | '-') term)*
+    pub fn expr(&mut self) -> Result<Expr, ParseError> {
+        self.sum().map(|(expr, _)| expr)
+    }
+
+    /// Same as expr, with the depth of the tree
+    fn sum(&mut self) -> Result<Tree, ParseError> {
+        let mut left = self.term()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Plus) => Operator::Add,
+                Some(Token::Minus) => Operator::Sub,
+                _ => return Ok(left),
+            };
+            let column = self.column();
+            self.next();
+            let right = self.term()?;
+            left = binary(column, operator, left, right)?;
+        }
+    }
+
+    /// term := factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Tree, ParseError> {
+        let mut left = self.factor()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Star) => Operator::Mul,
+                Some(Token::Slash) => Operator::Div,
+                _ => return Ok(left),
+            };
+            let column = self.column();
+            self.next();
+            let right = self.factor()?;
+            left = binary(column, operator, left, right)?;
+        }
+    }
+
+    /// factor := number | reference | occur | aggregate | '(' expr ')'
+    fn factor(&mut self) -> Result<Tree, ParseError> {
+        let column = self.column();
+        match self.peek() {
+            Some(Token::Number(number)) => {
+                let number = *number;
+                self.next();
+                Ok((Expr::Number(number), 1))
+            }
+            Some(Token::Ident(_)) if self.peek_second() == Some(&Token::LParen) => Ok((self.aggregate()?, 1)),
+            Some(Token::Ident(_)) => Ok((Expr::Ref(self.reference()?), 1)),
+            Some(Token::Hash) => Ok((self.occur()?, 1)),
+            Some(Token::LParen) => {
+                if self.nesting == DEPTH_MAX {
+                    return Err(too_deep(column));
+                }
+                self.next();
+                self.nesting += 1;
+                let tree = self.sum()?;
+                self.nesting -= 1;
+                self.expect(Token::RParen)?;
+                Ok(tree)
+            }
+            Some(token) => Err(ParseError::new(column, &format!("unexpected {:?}", token))),
+            None => Err(ParseError::new(column, "unexpected end of input")),
+        }
+    }
+
+    /// reference := A1 | R1C1
+    fn reference(&mut self) -> Result<Coordinates, ParseError> {
+        let column = self.column();
+        match self.next() {
+            Some(Token::Ident(ident)) => Coordinates::parse(&ident)
+                .ok_or_else(|| ParseError::new(column, &format!("invalid cell reference {}", ident))),
+            Some(token) => Err(ParseError::new(column, &format!("expected a cell reference, found {:?}", token))),
+            None => Err(ParseError::new(column, "expected a cell reference, found end of input")),
+        }
+    }
+
+    /// rectangle := number ',' number ',' number ',' number | reference ':' reference
+    fn rectangle(&mut self) -> Result<Rectangle, ParseError> {
+        if let Some(Token::Ident(_)) = self.peek() {
+            let begin = self.reference()?;
+            self.expect(Token::Colon)?;
+            let end = self.reference()?;
+            return Ok(Rectangle::from(begin, end));
+        }
+
+        let mut bounds = [0; 4];
+        for (i, bound) in bounds.iter_mut().enumerate() {
+            if i > 0 {
+                self.expect(Token::Comma)?;
+            }
+            *bound = self.number()?;
+        }
+        Ok(Rectangle::from(Coordinates::from(bounds[0], bounds[1]),
+                           Coordinates::from(bounds[2], bounds[3])))
+    }
+
+    /// occur := '#' '(' rectangle ',' predicate ')'
+    fn occur(&mut self) -> Result<Expr, ParseError> {
+        self.expect(Token::Hash)?;
+        self.expect(Token::LParen)?;
+        let rectangle = self.rectangle()?;
+        self.expect(Token::Comma)?;
+        let predicate = self.predicate()?;
+        self.expect(Token::RParen)?;
+        Ok(Expr::Occur(rectangle, predicate))
+    }
+
+    /// predicate := value | ('<' | '<=' | '>' | '>=' | '!=') value | '[' value '..' value ']'
+    fn predicate(&mut self) -> Result<Predicate, ParseError> {
+        let column = self.column();
+        let predicate = match self.peek() {
+            Some(Token::Less) => Predicate::Less,
+            Some(Token::LessEqual) => Predicate::LessEqual,
+            Some(Token::Greater) => Predicate::Greater,
+            Some(Token::GreaterEqual) => Predicate::GreaterEqual,
+            Some(Token::NotEqual) => Predicate::NotEqual,
+            Some(Token::LBracket) => {
+                self.next();
+                let low = self.value()?;
+                self.expect(Token::DotDot)?;
+                let high = self.value()?;
+                self.expect(Token::RBracket)?;
+                if low > high {
+                    return Err(ParseError::new(column, "empty interval"));
+                }
+                return Ok(Predicate::Between(low, high));
+            }
+            _ => return Ok(Predicate::Equal(self.value()?)),
+        };
+        self.next();
+        Ok(predicate(self.value()?))
+    }
+
+    /// aggregate := ('SUM' | 'MIN' | 'MAX' | 'AVERAGE') '(' rectangle ')'
+    fn aggregate(&mut self) -> Result<Expr, ParseError> {
+        let column = self.column();
+        let function = match self.next() {
+            Some(Token::Ident(name)) => Function::from_name(&name)
+                .ok_or_else(|| ParseError::new(column, &format!("unknown function {}", name)))?,
+            _ => return Err(ParseError::new(column, "expected a function name")),
+        };
+        self.expect(Token::LParen)?;
+        let rectangle = self.rectangle()?;
+        self.expect(Token::RParen)?;
+        Ok(Expr::Aggregate(function, rectangle))
+    }
+
+    /// command := (number number | reference) datum
+    pub fn command(&mut self) -> Result<Command, ParseError> {
+        let coordinates = if let Some(Token::Ident(_)) = self.peek() {
+            self.reference()?
+        } else {
+            let row = self.number()?;
+            let col = self.number()?;
+            Coordinates::from(row, col)
+        };
+        let datum = self.datum()?;
+        Ok(Command {
+            coordinates,
+            datum,
+        })
+    }
+}
+
+/// Join two expressions with an operator
+/// column : position of the operator, reported if the result is too deep
+fn binary(column: usize, operator: Operator, (left, left_depth): Tree, (right, right_depth): Tree)
+          -> Result<Tree, ParseError> {
+    let depth = left_depth.max(right_depth) + 1;
+    if depth > DEPTH_MAX {
+        return Err(too_deep(column));
+    }
+    Ok((Expr::Binary(operator, Box::new(left), Box::new(right)), depth))
+}
+
+/// Parse the content of a cell from the data file
+pub fn parse_datum(input: &str) -> Result<Datum, ParseError> {
+    let mut parser = Parser::new(input)?;
+    let datum = parser.datum()?;
+    parser.end_of_input()?;
+    Ok(datum)
+}
+
+/// Parse a plain value between 0 and 255, a formula is rejected
+pub fn parse_value(input: &str) -> Result<u32, ParseError> {
+    match parse_datum(input)? {
+        Datum::Value(value) => Ok(value),
+        Datum::Formula(_) => Err(ParseError::new(1, "expected a value, found a formula")),
+    }
+}
+
+/// Parse a line of the user file
+pub fn parse_command(input: &str) -> Result<Command, ParseError> {
+    let mut parser = Parser::new(input)?;
+    let command = parser.command()?;
+    parser.end_of_input()?;
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn occur(r1: u32, c1: u32, r2: u32, c2: u32, value: u32) -> Datum {
+        let rectangle = Rectangle::from(Coordinates::from(r1, c1), Coordinates::from(r2, c2));
+        Datum::Formula(Expr::Occur(rectangle, Predicate::Equal(value)))
+    }
+
+    fn error(result: Result<impl fmt::Debug, ParseError>) -> (usize, String) {
+        let error = result.unwrap_err();
+        (error.column, error.message)
+    }
+
+    #[test]
+    fn data_and_user_lines_ignore_the_same_whitespaces() {
+        for datum in ["=#(0,0,1,1,3)", " =#( 0 ,0,1 , 1,3 ) ", "\t= # (0,\t0,1,1,\t3)", "=#(0, 0, 1, 1, 3)\t"] {
+            assert_eq!(parse_datum(datum), Ok(occur(0, 0, 1, 1, 3)), "{:?}", datum);
+            let command = parse_command(&format!("2 4 {}", datum)).unwrap();
+            assert_eq!(command.coordinates, Coordinates::from(2, 4));
+            assert_eq!(command.datum, occur(0, 0, 1, 1, 3), "{:?}", datum);
+        }
+        assert_eq!(parse_datum(" 12\t"), Ok(Datum::Value(12)));
+        assert_eq!(parse_command("  2\t4   12 ").unwrap().datum, Datum::Value(12));
+    }
+
+    #[test]
+    fn spaces_do_not_join_numbers() {
+        assert_eq!(error(parse_datum("1 2")), (3, "unexpected Number(2)".to_string()));
+        assert_eq!(error(parse_command("0 0 1 2")), (7, "unexpected Number(2)".to_string()));
+    }
+
+    #[test]
+    fn values_are_limited_to_255() {
+        assert_eq!(parse_datum("255"), Ok(Datum::Value(255)));
+        assert_eq!(error(parse_datum("256")), (1, "value must be between 0 and 255".to_string()));
+        assert_eq!(error(parse_command("0 0 =#(0,0,1,1,256)")).1, "value must be between 0 and 255");
+        assert_eq!(error(parse_command("0 0 4294967295")).1, "value must be between 0 and 255");
+    }
+
+    #[test]
+    fn numbers_beyond_u32_are_too_large() {
+        let max = parse_command("4294967295 4294967295 1").unwrap();
+        assert_eq!(max.coordinates, Coordinates::from(u32::MAX, u32::MAX));
+        assert_eq!(error(parse_command("4294967296 0 1")), (1, "number too large".to_string()));
+        assert_eq!(error(parse_datum("=#(0,0,99999999999,1,1)")), (8, "number too large".to_string()));
+        assert_eq!(error(parse_datum("=A1+4294967296")), (5, "number too large".to_string()));
+    }
+
+    #[test]
+    fn malformed_occurrences_are_rejected() {
+        let cases = [
+            ("=#(", 4, "expected a number, found end of input"),
+            ("=#(0,0,1,1)", 11, "expected Comma, found RParen"),
+            ("=#(1,2,3)", 9, "expected Comma, found RParen"),
+            ("=#(0,0,1,1,3", 13, "expected RParen, found end of input"),
+            ("=#(0,0,1,1,3))", 14, "unexpected RParen"),
+            ("=#0,0,1,1,3)", 3, "expected LParen, found Number(0)"),
+            ("=#(0;0,1,1,3)", 5, "unexpected character ';'"),
+            ("=#(0,0,1,1,[5..2])", 12, "empty interval"),
+            ("=#(0,0,1,1,-1)", 12, "expected a number, found Minus"),
+        ];
+        for (datum, column, message) in cases {
+            assert_eq!(error(parse_datum(datum)), (column, message.to_string()), "{:?}", datum);
+        }
+    }
+
+    #[test]
+    fn reversed_rectangles_are_parsed_and_left_to_the_evaluation() {
+        assert_eq!(parse_datum("=#(2,2,0,0,1)"), Ok(occur(2, 2, 0, 0, 1)));
+    }
+
+    #[test]
+    fn expressions_are_nested_up_to_depth_max() {
+        let parentheses = |depth| format!("={}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse_datum(&parentheses(DEPTH_MAX)).is_ok());
+        assert_eq!(error(parse_datum(&parentheses(20000))), (DEPTH_MAX + 2, "expression nested too deeply".to_string()));
+
+        let chain = |terms| format!("=1{}", "+1".repeat(terms));
+        assert!(parse_datum(&chain(DEPTH_MAX - 1)).is_ok());
+        assert_eq!(error(parse_datum(&chain(DEPTH_MAX))), (2 * DEPTH_MAX + 1, "expression nested too deeply".to_string()));
+        assert_eq!(error(parse_datum(&chain(300000))).1, "expression nested too deeply");
+
+        // Operators inside parentheses add to the depth of the operators around them
+        let nested = format!("={}1{}", "(1+".repeat(DEPTH_MAX - 1), ")".repeat(DEPTH_MAX - 1));
+        assert!(parse_datum(&nested).is_ok());
+        assert_eq!(error(parse_datum(&format!("=1*{}", &nested[1..]))).1, "expression nested too deeply");
+    }
+}
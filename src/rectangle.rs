@@ -25,14 +25,8 @@ impl Rectangle {
     }
 
 
-    pub fn rect_respecting_max(&mut self, row_max: u32, col_max: u32) -> bool{
-        return self.end.col <= col_max && self.end.row <= row_max
-    }
-
     pub fn is_valid(&self) -> bool {
         //println!("b_row {} e_row {} b_col {} e_col {}", self.begin.row, self.end.row, self.begin.col, self.end.col);
-        (self.begin.row >= 0 && self.begin.col >= 0)
-            && (self.begin.row <= self.end.row && self.begin.col <= self.end.col)
+        self.begin.row <= self.end.row && self.begin.col <= self.end.col
     }
-
 }
\ No newline at end of file
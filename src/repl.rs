@@ -0,0 +1,141 @@
+use crate::coordinates::Coordinates;
+use crate::error::Error;
+use crate::parser::{self, ParseError};
+use crate::rectangle::Rectangle;
+use crate::spreadsheet::{SpreadSheet, Snapshot};
+use std::io::{BufRead, Write};
+
+const HELP: &str = "\
+r c d          write the datum d in the cell r c, d being a value or a formula
+A1 d           same, the cell written with the A1 or R1C1 notation
+get r c        print the result of a cell, or get A1
+show r1 c1 r2 c2
+               print the results of a rectangle, or show A1 B2
+undo           cancel the last change
+help           print this help
+quit           leave, as the end of the input does
+";
+
+/// Interactive session over a loaded sheet
+/// sheet : the sheet, already evaluated
+/// history : every change applied, with the cell and the shape of the sheet before it, the last one at the end
+pub struct Repl {
+    sheet: SpreadSheet,
+    history: Vec<Snapshot>,
+}
+
+impl Repl {
+
+    pub fn new(sheet: SpreadSheet) -> Repl {
+        Repl {
+            sheet,
+            history: Vec::new(),
+        }
+    }
+
+    /// The sheet, as the commands left it
+    pub fn sheet(&self) -> &SpreadSheet {
+        &self.sheet
+    }
+
+    /// Read commands until the end of the input or "quit", and write their outputs
+    /// A command which can't be run is reported, and the session goes on
+    ///
+    /// # Arguments
+    /// * 'input' - Where the commands are read, one per line.
+    /// * 'output' - Where the changes, results and errors are written.
+    /// * 'prompt' - Written before every command, empty when the input is not typed by someone.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W, prompt: &str) -> Result<(), Error> {
+        let mut lines = input.lines();
+        loop {
+            write!(output, "{}", prompt)?;
+            output.flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => break,
+            };
+            let line = line.trim();
+            match line {
+                "" => continue,
+                "quit" | "exit" => break,
+                "help" => write!(output, "{}", HELP)?,
+                line => {
+                    if let Err(error) = self.execute(line, &mut output) {
+                        // A failed output is not a wrong command
+                        if let Error::Io(_) = error {
+                            return Err(error);
+                        }
+                        writeln!(output, "ERROR - {}", error)?;
+                    }
+                }
+            }
+        }
+        Ok(output.flush()?)
+    }
+
+    /// Run a single command other than help and quit
+    pub fn execute<W: Write>(&mut self, line: &str, output: &mut W) -> Result<(), Error> {
+        let (name, arguments) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        // Column of the arguments, to report where they are wrong
+        let column = name.len() + 2;
+        match name {
+            "get" => {
+                let coordinates = cell_arguments(arguments, 1, column)?[0];
+                writeln!(output, "{}", self.sheet.cell_string(&coordinates, self.sheet.get_result(&coordinates)))?;
+                Ok(())
+            }
+            "show" => {
+                let corners = cell_arguments(arguments, 2, column)?;
+                let rectangle = Rectangle::from(corners[0], corners[1]);
+                if !rectangle.is_valid() {
+                    return Err(ParseError::new(column, "the first cell must be above and left of the second one").into());
+                }
+                self.sheet.write_area(&rectangle, output)
+            }
+            "undo" => self.undo(output),
+            _ => self.edit(line, output),
+        }
+    }
+
+    /// Apply a line like "r c d", and write its changes
+    fn edit<W: Write>(&mut self, line: &str, output: &mut W) -> Result<(), Error> {
+        let command = parser::parse_command(line)?;
+        let previous = self.sheet.snapshot(command.coordinates);
+        self.sheet.apply(&command)?;
+        self.history.push(previous);
+        let changes = self.sheet.take_changes();
+        self.sheet.write_changes(line, &changes, output)
+    }
+
+    /// Give back to the last changed cell what it held before, and write the changes
+    fn undo<W: Write>(&mut self, output: &mut W) -> Result<(), Error> {
+        let previous = match self.history.pop() {
+            Some(previous) => previous,
+            None => {
+                writeln!(output, "nothing to undo")?;
+                return Ok(());
+            }
+        };
+        self.sheet.restore(previous)?;
+        let changes = self.sheet.take_changes();
+        self.sheet.write_changes("undo", &changes, output)
+    }
+}
+
+/// Read a number of cells, each one written "r c" or with the A1 or R1C1 notation
+fn cell_arguments(arguments: &str, count: usize, column: usize) -> Result<Vec<Coordinates>, Error> {
+    let words: Vec<&str> = arguments.split_whitespace().collect();
+    let cells: Option<Vec<Coordinates>> = if words.len() == 2 * count {
+        words.chunks(2)
+            .map(|pair| Some(Coordinates::from(pair[0].parse().ok()?, pair[1].parse().ok()?)))
+            .collect()
+    } else if words.len() == count {
+        words.iter().map(|word| Coordinates::parse(&word.to_ascii_uppercase())).collect()
+    } else {
+        None
+    };
+    cells.ok_or_else(|| {
+        let expected = if count == 1 { "a cell, as r c or A1" } else { "two cells, as r1 c1 r2 c2 or A1 B2" };
+        Error::from(ParseError::new(column, &format!("expected {}", expected)))
+    })
+}
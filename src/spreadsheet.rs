@@ -1,190 +1,343 @@
 extern crate petgraph;
+extern crate rayon;
 use crate::cell::*;
-use crate::coordinates::Coordinates;
-use std::io::{BufReader, BufRead, Write, Error};
-use std::fs::{File, OpenOptions};
-use self::petgraph::{Directed, Graph};
-use std::collections::{HashMap, BTreeMap};
-use petgraph::graph::NodeIndex;
-use self::petgraph::algo::{toposort};
-use crate::cell::Category::FaultyCell;
-use crate::rules::Rules;
+use crate::coordinates::{Coordinates, Notation};
+use crate::rectangle::Rectangle;
+use crate::dependencies::Dependencies;
+use crate::occurrences::OccurrenceIndex;
+use crate::grid::Grid;
+use crate::error::Error;
+use crate::format::{self, Format};
+use crate::grid::COORDINATE_MAX;
+use std::io::{BufRead, BufWriter, Write};
+use std::collections::{HashMap, BTreeMap, BTreeSet};
+use self::petgraph::algo::kosaraju_scc;
+use self::petgraph::graphmap::DiGraphMap;
+use self::petgraph::visit::Dfs;
+use self::rayon::prelude::*;
+use crate::parser::{self, Command, Datum, Expr, Operator, Predicate, VALUE_MAX};
 use std::ops::Add;
-use self::petgraph::Direction::Incoming;
 
+/// Biggest result of an expression, as it is computed
+const MAX_RESULT: i64 = VALUE_MAX as i64;
+
+/// The old and the new value of a cell, None for a faulty cell
+type Change = (Option<u32>, Option<u32>);
+
+/// What a cell held before a change, to give it back on undo
+/// Datum : a value, or a formula kept even when the cell became faulty
+/// Faulty : a content which could not be parsed
+/// Empty : nothing
+#[derive(Debug, Clone)]
+enum Content {
+    Datum(Datum),
+    Faulty(Category),
+    Empty,
+}
+
+/// A cell and the shape of the sheet before a change of the cell, to give them back on undo
+/// width : the number of values of the row of the cell in the view, None if the row was not printed
+/// row_max, col_max : the last row and column of the sheet
+#[derive(Debug, Clone)]
+pub(crate) struct Snapshot {
+    coordinates: Coordinates,
+    content: Content,
+    width: Option<u32>,
+    row_max: u32,
+    col_max: u32,
+}
 
 /// Spreadsheet contain :
-/// graph :  where to store the cells
-/// evals : hashmap to store only the cell to evaluate, which mean OccurCells
-/// changes : to store the changes affected by a user
+/// grid : where to store the cells, found from their coordinates
+/// dependencies : the areas read by every formula cell
+/// evals : the formula cells of the data file waiting for their first evaluation
+/// changes : the new result of every cell changed by the user, since the changes were last taken, None for an emptied cell
+/// sources : the parsed formula of every formula cell, kept even when the cell becomes faulty
+/// row_max, col_max : the last row and column of the sheet, they grow when a cell is written beyond
+/// widths : the number of values of each row, as in the data file or up to the last cell written, the view is printed with the same shape
+/// A row missing from widths is printed empty
+/// padding : the value of the cells missing at the end of a shorter row, None to leave them empty
+/// notation : how coordinates are written in the changes file
+/// format : how the outputs are written
+/// show_errors : print the kind of error of faulty cells instead of "P"
+/// propagation : how a formula reading a faulty cell is evaluated
+/// indexed : build the occurrence index when the sheet is evaluated
+/// occurrences : optional index of the values, to count occurrences without browsing the cells
 pub struct SpreadSheet {
-    pub graph: Graph<Cell, Cell, Directed>,
-    pub evals: HashMap<Coordinates, Cell>,
-    pub changes: BTreeMap<Coordinates, String>,
-    pub col_max: u32,
-    pub row_max: u32
+    grid: Grid,
+    dependencies: Dependencies,
+    evals: HashMap<Coordinates, Cell>,
+    changes: BTreeMap<Coordinates, Option<Result<u32, CellError>>>,
+    sources: HashMap<Coordinates, Datum>,
+    col_max: u32,
+    row_max: u32,
+    widths: BTreeMap<u32, u32>,
+    padding: Option<u32>,
+    notation: Notation,
+    format: Format,
+    show_errors: bool,
+    propagation: Propagation,
+    indexed: bool,
+    occurrences: Option<OccurrenceIndex>
+}
+
+impl Default for SpreadSheet {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SpreadSheet {
 
     pub fn new() -> SpreadSheet{
         SpreadSheet{
-            graph: Graph::new(),
+            grid: Grid::new(),
+            dependencies: Dependencies::new(),
             evals: HashMap::new(),
             changes: BTreeMap::new(),
+            sources: HashMap::new(),
             col_max: 0,
-            row_max: 0
+            row_max: 0,
+            widths: BTreeMap::new(),
+            padding: None,
+            notation: Notation::Numeric,
+            format: Format::Text,
+            show_errors: false,
+            propagation: Propagation::Propagate,
+            indexed: false,
+            occurrences: None
         }
     }
 
-    /// Function that browse "data.csv" file input in fill the graph
-    pub fn browse_data(&mut self, path: &String) {
-        let stream = BufReader::new(File::open(path).unwrap());
-        let rule = Rules::new();
+    /// Set how coordinates are written in the changes file
+    pub fn set_notation(&mut self, notation: Notation) {
+        self.notation = notation;
+    }
+
+    /// Set how the outputs are written
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    /// Print the kind of error of faulty cells instead of "P"
+    pub fn set_show_errors(&mut self, show_errors: bool) {
+        self.show_errors = show_errors;
+    }
+
+    /// Set how a formula reading a faulty cell is evaluated
+    pub fn set_propagation(&mut self, propagation: Propagation) {
+        self.propagation = propagation;
+    }
+
+    /// Set the value of the cells missing at the end of a shorter row, before reading the data
+    pub fn set_padding(&mut self, padding: Option<u32>) {
+        self.padding = padding;
+    }
+
+    /// Build the occurrence index when the sheet is evaluated
+    pub fn set_indexed(&mut self, indexed: bool) {
+        self.indexed = indexed;
+    }
+
+    /// Fill the grid from data in the "data.csv" format, one row per line and values separated by ';'
+    /// A blank value is an empty cell, and a row may have less values than others
+    /// The cells missing at the end of a row get the padding value, if there is one
+    pub fn read_data<R: BufRead>(&mut self, reader: R) -> Result<(), Error> {
 
         // Index on the csv file
         let mut coordinates = Coordinates::new();
-        for line in stream.lines() {
+        for line in reader.lines() {
 
-            for value in line.unwrap().split(';'){
+            for value in line?.split(';'){
+
+                // Nothing is stored for an empty cell
+                if value.trim().is_empty() {
+                    coordinates.col += 1;
+                    continue;
+                }
 
                 // Parse the data and build a brand new cell
                 let mut new_cell = Cell::new();
-                let new_category = Cell::from_formula(&value.to_string(), &rule);
+                let new_category = match parser::parse_datum(value) {
+                    Ok(datum) => {
+                        self.set_source(coordinates, &datum);
+                        Cell::from_datum(&datum)
+                    }
+                    Err(_) => Category::FaultyCell(CellError::Syntax),
+                };
 
                 //println!("cat 2: {:?}",new_category);
                 new_cell.set_category(new_category);
                 new_cell.set_coordinates(coordinates);
 
-                // add the cell into the graphe as a node
-                let index  = self.graph.add_node(new_cell);
-                new_cell.set_index(index);
-                self.graph[index].set_index(index);
+                // add the cell into the grid
+                self.grid.set_category(coordinates, new_cell.category.clone());
 
                 // If the new cell need an evaluation, add it into the evaluation set
-                if let Category::OccurCell(_cell) = new_category {
+                if new_cell.is_formula() {
                     self.evals.insert(coordinates, new_cell);
                 }
 
                 // Move the cursor to the next column
                 coordinates.col += 1;
             }
-            self.col_max = coordinates.col - 1;
+            self.col_max = self.col_max.max(coordinates.col - 1);
+            self.widths.insert(coordinates.row, coordinates.col);
             coordinates.col = 0;
             // End of line, move the cursor to the next row
             coordinates.row += 1;
         }
-        self.row_max = coordinates.row - 1;
-    }
-
-    /// Function that give us an index node from coordinates
-    /// For exemple in a file with max_col = 4
-    /// If we want to have the index node of the cell with coordinate (2, 2)
-    /// It will be ((4 + 1)*2 + 2) = 12
-    pub fn get_index_node_from_crd(&mut self, coordinates: &Coordinates) -> NodeIndex<u32>{
-        return if coordinates.row == 0 {
-            NodeIndex::new(coordinates.col as usize)
-        } else {
-            let res = ((self.col_max + 1) * coordinates.row) + coordinates.col;
-            NodeIndex::new(res as usize)
-        }
-    }
+        self.row_max = coordinates.row.saturating_sub(1);
 
-    /// Function that link nodes between them
-    /// An OccurCell is a father of every another cell in it rectangle (area)
-    /// Every node containing an OccurCell will build an outgoing edges to it children
-    pub fn link_nodes(&mut self){
-        let clone = self.evals.clone();
-        for (coordinates,  mut cell) in clone {
-            let first_index = cell.get_index();
-            let category = cell.category;
-            if let Category::OccurCell(mut cat) = category {
-                if ! cat.rectangle.rect_respecting_max(self.row_max, self.col_max) {
-                    let index = cell.clone().get_index();
-                    self.graph[index].set_category(Category::FaultyCell);
-                    self.evals.remove(&coordinates);
-                } else {
-                    for row in cat.rectangle.begin.row..(cat.rectangle.end.row + 1) {
-                        for col in cat.rectangle.begin.col..(cat.rectangle.end.col + 1){
-                            let crd = Coordinates::from(row,col);
-                            let second_index = self.get_index_node_from_crd(&crd);
-                            self.graph.extend_with_edges(&[
-                                (first_index, second_index)
-                            ]);
-                        }
-                    }
+        if let Some(value) = self.padding {
+            for (&row, &width) in self.widths.iter() {
+                for col in width..(self.col_max + 1) {
+                    let padding = Category::StaticCell(StaticCell::from(value));
+                    self.grid.set_category(Coordinates::from(row, col), padding);
                 }
             }
         }
+        Ok(())
     }
 
-    /// Return the family of a node from it index
-    pub fn get_family(&mut self, node_index: NodeIndex<u32>, memo: &mut Vec<NodeIndex<u32>>) {
-        if let Category::StaticCell(_cell) = self.graph[node_index].category{
-            return;
-        }
-        if memo.contains(&node_index) {
-            return;
+    /// Evaluate every cell of the sheet once it is read
+    /// Cells on a cycle become faulty, the cells reading them follow the propagation policy
+    pub fn evaluate(&mut self) {
+        // link the formulas to the areas they read
+        self.link_nodes();
+
+        // Gather every cell on a cycle, and only those
+        // Cells reading a cycle are evaluated later following the propagation policy
+        let cell_to_update = self.find_cycles();
+
+        // Update cells that have to be changed into FaultyCell
+        self.update_cells(cell_to_update);
+
+        // The values known before any evaluation are indexed
+        if self.indexed {
+            self.build_occurrences();
         }
-        memo.push(node_index);
 
-        let clone = self.graph.clone();
-        let list = clone.neighbors_undirected(node_index);
-        for element in list {
-            self.get_family(element,memo);
+        // Evaluate all cells
+        self.evaluate_all();
+    }
+
+    /// Keep the formula of a cell, so it can be given back if the cell becomes faulty
+    /// A plain value has nothing to keep
+    fn set_source(&mut self, coordinates: Coordinates, datum: &Datum) {
+        match datum {
+            Datum::Formula(_) => { self.sources.insert(coordinates, datum.clone()); }
+            Datum::Value(_) => { self.sources.remove(&coordinates); }
         }
     }
 
-    /// If the graph was acyclic, return a vector of nodes in topological order: each node is ordered before its successors.
-    /// Otherwise, it will return a Cycle error. Self loops are also cycles.
-    pub fn check_cycle(&mut self, cloned_graph: &mut Graph<Cell, Cell, Directed>) -> Option<Vec<NodeIndex<u32>>> {
-        let potential_cycle = toposort(&cloned_graph.clone(), None);
-        match potential_cycle {
-            Ok(_) => None, // no cycle found
-            Err(e) => {
-                let node_id = e.node_id(); // Return a node id that participates in the cycle
-                let mut family: Vec<NodeIndex<u32>> = Vec::new();
-                self.get_family(node_id,&mut family);
-                Some(family)
-            },
+    /// Function that link nodes between them
+    /// A formula cell is a father of every another cell in the areas it reads
+    /// Every node containing a formula records the areas it reads in the dependencies
+    fn link_nodes(&mut self){
+        let clone = self.evals.clone();
+        for (_, cell) in clone {
+            self.link_node(&cell);
         }
     }
 
-    /// Printing the cells from the graph
-    pub fn print_cells(&mut self) {
+    /// Link a single formula cell to the areas it reads
+    /// An area may go beyond the cells of the sheet, the cells there are read as empty
+    fn link_node(&mut self, cell: &Cell) {
+        self.dependencies.insert(cell.coordinates, cell.get_areas());
+    }
 
-        for index in 0..(self.graph.node_count()) {
-            let mut cell =  self.graph[NodeIndex::new(index)];
+    /// Every cell reading one of the nodes, directly or through other cells
+    /// A node is part of it only if it reads one of them, like a node on a cycle
+    fn get_readers(&self, nodes: &[Coordinates]) -> BTreeSet<Coordinates> {
+        let mut readers = BTreeSet::new();
+        let mut stack = nodes.to_vec();
+        while let Some(coordinates) = stack.pop() {
+            for father in self.dependencies.fathers(&coordinates) {
+                if readers.insert(father) {
+                    stack.push(father);
+                }
+            }
+        }
+        readers
+    }
 
-            if let Category::OccurCell(_cell) = cell.category {
+    /// Return every cell on a cycle, and only those.
+    ///
+    /// A cell is on a cycle if its strongly connected component holds more than one cell,
+    /// or if it reads itself.
+    /// Cells reading a cycle without being part of it are left to the propagation policy.
+    fn find_cycles(&self) -> Vec<Coordinates> {
+        // Only a formula can be on a cycle
+        let formulas: Vec<Coordinates> = self.dependencies.formulas().collect();
+        self.find_cycles_among(&formulas)
+    }
 
-                println!("[OCCUR ] node with index : {} is {:?}", index , self.graph[cell.get_index()]);
-                println!();
+    /// Return the cells on a cycle going only through the given cells
+    fn find_cycles_among(&self, cells: &[Coordinates]) -> Vec<Coordinates> {
+        let formulas = self.formula_graph(cells);
+
+        // Kosaraju's algorithm is not recursive, a long chain of formulas can't overflow the stack
+        let mut cycles = Vec::new();
+        for component in kosaraju_scc(&formulas) {
+            let is_cycle = component.len() > 1
+                || formulas.contains_edge(component[0], component[0]);
+            if is_cycle {
+                cycles.extend(component);
+            }
+        }
+        cycles
+    }
 
-                let neighbors = self.graph.neighbors(cell.get_index());
-                println!("\tCHILDREN OF INDEX NUMBER {} ARE : ",index);
-                for element in neighbors {
-                    println!("node with index : {} is {:?}", element.index() , self.graph[element]);
+    /// Graph of the dependencies between some cells, from each father to its children
+    /// A cell reading cells outside of the given ones has no edge to them
+    fn formula_graph(&self, cells: &[Coordinates]) -> DiGraphMap<Coordinates, ()> {
+        let mut graph = DiGraphMap::new();
+        for &child in cells {
+            graph.add_node(child);
+        }
+        // Looking for the fathers only reads the dependencies, it is done in parallel
+        let fathers: Vec<Vec<Coordinates>> = cells.par_iter()
+            .map(|child| self.dependencies.fathers(child))
+            .collect();
+        for (&child, fathers) in cells.iter().zip(fathers) {
+            for father in fathers {
+                if graph.contains_node(father) {
+                    graph.add_edge(father, child, ());
                 }
-                println!();
-
-            } else {
-                println!("[STATIC] node with index : {} is {:?}", index , self.graph[cell.get_index()]);
-                println!();
-
             }
         }
+        graph
     }
 
+    /// Return the cells sharing a cycle with a node, the node included
+    /// Those cells are read by the node and read it back
+    /// Return None if the node is not on a cycle
+    fn get_cycle(&self, coordinates: Coordinates) -> Option<Vec<Coordinates>> {
+        let readers = self.get_readers(&[coordinates]);
+        if !readers.contains(&coordinates) {
+            return None;
+        }
+
+        // Every cell on a path from the node to one of its readers reads the node too
+        // So the cells read by the node are only looked for among its readers
+        let readers: Vec<Coordinates> = readers.into_iter().collect();
+        let graph = self.formula_graph(&readers);
+        let mut component = Vec::new();
+        let mut dfs = Dfs::new(&graph, coordinates);
+        while let Some(reader) = dfs.next(&graph) {
+            component.push(reader);
+        }
+        Some(component)
+    }
 
     /// Update the category of cells who are part of a cycle.
     /// A cell in a cycle is a FaultyCell
     /// # Arguments
-    /// * 'cycle_cells' A NodeIndex vector of cell who are part of a cycle
-    pub fn update_cells(&mut self, cycle_cells: Vec<NodeIndex<u32>>) {
-        for key in cycle_cells.clone() {
-            self.graph[key].set_category(FaultyCell);
-            let coordinates = self.graph[key].coordinates;
+    /// * 'cycle_cells' The coordinates of the cells who are part of a cycle
+    fn update_cells(&mut self, cycle_cells: Vec<Coordinates>) {
+        for coordinates in cycle_cells {
+            self.grid.set_category(coordinates, Category::FaultyCell(CellError::Cycle));
             self.evals.remove(&coordinates);
         }
 
@@ -192,376 +345,812 @@ impl SpreadSheet {
 
     /// Browse evals hashmap and evaluates all the cells inside.
     ///
-    /// First, evaluate all cells without childs into the list.
-    /// Then, delete those cells from the list.
-    /// Repeat until there is no cells into the list.
+    /// First, count for each cell how many cells of the list it reads.
+    /// The cells reading none of them are the first level.
+    /// The cells of a level are evaluated in parallel, then deleted from the list.
+    /// A cell whose count falls to zero belongs to the next level.
+    /// Cells on a cycle must be out of the list, they would never be ready.
     ///
-    /// # Return value
-    /// Nothing if everything was alright, else Error.
-    pub fn evaluate_all(&mut self)  {
-        while !self.evals.is_empty() {
+    /// The cells of a level don't read each other,
+    /// so the result is the same whatever the number of threads.
+    fn evaluate_all(&mut self)  {
+        let fathers: Vec<(Coordinates, Vec<Coordinates>)> = self.evals.par_iter()
+            .map(|(&coordinates, _)| (coordinates, self.dependencies.fathers(&coordinates)))
+            .collect();
+        let mut waiting: HashMap<Coordinates, usize> = fathers.iter()
+            .map(|&(coordinates, _)| (coordinates, 0))
+            .collect();
+        for father in fathers.into_iter().flat_map(|(_, fathers)| fathers) {
+            if let Some(count) = waiting.get_mut(&father) {
+                *count += 1;
+            }
+        }
 
-            for (crd, mut cell) in self.evals.clone().iter() {
+        let mut level: Vec<Coordinates> = waiting.iter()
+            .filter(|(_, &count)| count == 0)
+            .map(|(&coordinates, _)| coordinates)
+            .collect();
+        while !level.is_empty() {
+            // The grid is only read while the level is evaluated
+            level.sort();
+            let evaluated: Vec<_> = level.par_iter()
+                .map(|&coordinates| {
+                    (coordinates, self.evaluated(coordinates), self.dependencies.fathers(&coordinates))
+                })
+                .collect();
+
+            let mut next = Vec::new();
+            for (coordinates, category, fathers) in evaluated {
+                if let Some(category) = category {
+                    self.grid.set_category(coordinates, category);
+                }
+                self.evals.remove(&coordinates);
+                if let Some(occurrences) = &mut self.occurrences {
+                    occurrences.add(&coordinates, self.grid.get_special(&coordinates));
+                }
 
-                if let Category::OccurCell(mut cat) = cell.category {
-                    if ! cat.rectangle.rect_respecting_max(self.row_max, self.col_max) {
-                        let index = cell.clone().get_index();
-                        self.graph[index].set_category(Category::FaultyCell);
-                        self.evals.remove(&crd);
+                for father in fathers {
+                    if let Some(count) = waiting.get_mut(&father) {
+                        *count -= 1;
+                        if *count == 0 {
+                            next.push(father);
+                        }
                     }
                 }
+            }
+            level = next;
+        }
+    }
 
-                let mut occ_child = false;
-                let children = self.graph.neighbors(cell.clone().get_index());
-                for child_index in children {
-                    let another_cell = self.graph[child_index];
-                    if self.evals.contains_key(&another_cell.coordinates) {
-                        occ_child = true;
-                    }
+    /// Evaluate the formula stored in a cell from the values of its children
+    fn evaluate_node(&mut self, coordinates: Coordinates) {
+        if let Some(category) = self.evaluated(coordinates) {
+            self.grid.set_category(coordinates, category);
+        }
+    }
+
+    /// Return the category of a cell once its formula is evaluated, None if it has no formula
+    /// An ExprCell result is an error when the evaluation failed or gave a value outside 0..255
+    /// The grid is only read, so many cells can be evaluated at the same time
+    fn evaluated(&self, coordinates: Coordinates) -> Option<Category> {
+        match self.grid.get_other(&coordinates)? {
+            Category::OccurCell(occ) => {
+                // dangerous block
+                // if cell block rectangle is not valid it s a faulty cell
+                let (occurrence, faulty_child) = self.count_occurrences(&occ.rectangle, &occ.predicate);
+                if faulty_child && self.propagation == Propagation::Propagate {
+                    return Some(Category::FaultyCell(CellError::Propagated));
                 }
-                if !occ_child {
-                    self.evaluate_cell(&crd);
-                    self.evals.remove(&crd);
+                Some(Category::OccurCell(OccurCell { occurrence, ..*occ }))
+            }
+            Category::AggregateCell(agg) => {
+                let mut agg = *agg;
+                agg.fill(&self.area_values(&agg.rectangle), self.propagation);
+                Some(Category::AggregateCell(agg))
+            }
+            Category::ExprCell(expr_cell) => {
+                // A result a cell can't hold is an error, and not the nearest value
+                let result = self.evaluate_expr(&expr_cell.expr)
+                    .and_then(|value| match value {
+                        0..=MAX_RESULT => Ok(value as u32),
+                        _ => Err(CellError::Arithmetic),
+                    });
+                Some(Category::ExprCell(ExprCell { result, ..expr_cell.clone() }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Update an aggregate cell when some of its children go from old to new
+    /// The area is browsed again only if the update can't be done from the old values
+    fn update_aggregate(&mut self, coordinates: Coordinates, changes: &[Change]) {
+        if let Some(&Category::AggregateCell(mut agg)) = self.grid.get_other(&coordinates) {
+            // With the Propagate policy, the sum and extremum are not kept while a child is faulty
+            let up_to_date = match self.propagation {
+                Propagation::Propagate => agg.result.is_ok()
+                    && changes.iter().all(|(old, new)| old.is_some() && new.is_some()),
+                Propagation::Skip => true,
+            };
+            if up_to_date && changes.iter().all(|&(old, new)| agg.update(old, new)) {
+                self.grid.set_category(coordinates, Category::AggregateCell(agg));
+                return;
+            }
+            self.evaluate_node(coordinates);
+        }
+    }
+
+    /// Count the cells of an area matching a predicate, and tell if one of them is faulty
+    /// With the occurrence index, the cells of the area are not browsed
+    /// The index only covers the sheet it was built with, the cells written beyond are browsed
+    fn count_occurrences(&self, rectangle: &Rectangle, predicate: &Predicate) -> (u32, bool) {
+        let (mut occurrence, mut faulty_child, outside) = match &self.occurrences {
+            Some(index) => (index.count(rectangle, predicate), index.has_faulty(rectangle), index.outside(rectangle)),
+            None => (0, false, vec![*rectangle]),
+        };
+        for area in outside {
+            let values = self.area_values(&area);
+            occurrence += values.iter()
+                .filter(|&&child| predicate.matches(child))
+                .count() as u32;
+            faulty_child |= values.contains(&None);
+        }
+        (occurrence, faulty_child)
+    }
+
+    /// Build the occurrence index from every cell which is not waiting to be evaluated
+    /// A formula is counted once it is evaluated
+    /// Above occurrences::CELLS_MAX cells there is no index and the areas are browsed
+    fn build_occurrences(&mut self) {
+        let mut index = match OccurrenceIndex::new(self.row_max, self.col_max) {
+            Some(index) => index,
+            None => return,
+        };
+        for row in 0..self.grid.row_count() {
+            for col in 0..self.grid.col_count() {
+                let coordinates = Coordinates::from(row, col);
+                if self.grid.contains(&coordinates) && !self.evals.contains_key(&coordinates) {
+                    index.add(&coordinates, self.grid.get_special(&coordinates));
                 }
             }
         }
+        self.occurrences = Some(index);
     }
 
-    /// evaluate one cell
-    pub fn evaluate_cell(&mut self, coordinates: &Coordinates)  {
+    /// Values of every cell inside an area, None for a faulty cell
+    /// An empty cell is left out
+    fn area_values(&self, rectangle: &Rectangle) -> Vec<Option<u32>> {
+        self.grid.results(rectangle)
+            .into_iter()
+            .map(|result| result.ok())
+            .collect()
+    }
 
-        if let Some(cell) = self.evals.get_mut(coordinates) {
-            // dangerous block
-            // if cell block rectangle is not valid it s a faulty cell
-            let children = self.graph.neighbors(cell.get_index());
-            let mut occurrence = 0;
-            for child in children {
-                let mut child_cell = self.graph[child];
-                if cell.get_value() == child_cell.get_special() {
-                    occurrence += 1;
+    /// Compute an expression from the values currently in the grid
+    /// Return an error if a referenced cell is faulty, on a division by zero or an overflow
+    /// An empty cell is worth zero
+    fn evaluate_expr(&self, expr: &Expr) -> Result<i64, CellError> {
+        match expr {
+            Expr::Number(number) => Ok(*number as i64),
+            Expr::Ref(coordinates) => match self.grid.get_result(coordinates) {
+                Some(Ok(value)) => Ok(value as i64),
+                Some(Err(_)) => Err(CellError::Propagated),
+                None => Ok(0),
+            },
+            Expr::Occur(rectangle, predicate) => {
+                let (occurrence, faulty_child) = self.count_occurrences(rectangle, predicate);
+                if self.propagation == Propagation::Propagate && faulty_child {
+                    return Err(CellError::Propagated);
                 }
+                Ok(occurrence as i64)
+            }
+            Expr::Aggregate(function, rectangle) => {
+                let mut agg = AggregateCell::from(*function, *rectangle);
+                agg.fill(&self.area_values(rectangle), self.propagation);
+                // Inside an expression the value is not limited to 255, like any other operand
+                match agg.result {
+                    Err(CellError::Propagated) => Err(CellError::Propagated),
+                    _ => agg.compute().map(|value| value as i64),
+                }
+            }
+            Expr::Binary(operator, left, right) => {
+                let left = self.evaluate_expr(left)?;
+                let right = self.evaluate_expr(right)?;
+                let result = match operator {
+                    Operator::Add => left.checked_add(right),
+                    Operator::Sub => left.checked_sub(right),
+                    Operator::Mul => left.checked_mul(right),
+                    Operator::Div => left.checked_div(right),
+                };
+                result.ok_or(CellError::Arithmetic)
             }
-            if occurrence > 255 { occurrence = 255; }
-            self.graph[cell.get_index()].set_occurrence(occurrence);
         }
     }
 
-    /// Print the nodes graph in a csv file.
+    /// Return the reason why the cell at these coordinates has no value, None if it has one
+    pub fn get_error(&self, coordinates: &Coordinates) -> Option<CellError> {
+        self.grid.get_error(coordinates)
+    }
+
+    /// Return the result as it is printed in the outputs, None for an empty cell
+    /// An empty cell prints nothing in text
+    fn result_string(&self, result: Option<Result<u32, CellError>>) -> String {
+        match self.format {
+            Format::Text => result.map_or(String::new(), |result| result_string(result, self.show_errors)),
+            Format::Json => format::json_result(result, self.show_errors),
+        }
+    }
+
+    /// Return a cell and its result as they are printed in the changes, "x y v"
+    /// An empty cell is printed "x y", with nothing after its coordinates
+    pub(crate) fn cell_string(&self, coordinates: &Coordinates, result: Option<Result<u32, CellError>>) -> String {
+        match result {
+            Some(_) => format!("{} {}", self.notation.format(coordinates), self.result_string(result)),
+            None => self.notation.format(coordinates),
+        }
+    }
+
+    /// Every cell holding something inside the rectangle, in row order
+    /// Only the cells of the sheet are browsed, the rectangle can be as large as wanted
+    fn area_cells(&self, rectangle: &Rectangle) -> Vec<Coordinates> {
+        if !rectangle.is_valid() {
+            return Vec::new();
+        }
+        let mut cells = self.grid.cells(rectangle);
+        cells.sort();
+        cells
+    }
+
+    /// Write the cells grid in the "view.csv" format.
     ///
     /// # Arguments
-    /// * 'path' - Path to the file where the data are written.
+    /// * 'writer' - Where the data are written.
     ///
     /// # Return value
     /// Nothing if everything was alright, else Error.
-    pub fn print_view(&self, path: &String) -> Result<(), Error> {
-        // Open the file where all the data will be write
-        let mut stream = OpenOptions::new()
-            .truncate(true)
-            .write(true)
-            .create(true)
-            .open(path)?;
-
-        for index in 0..(self.graph.node_count()) {
-
-            let mut cell =  self.graph[NodeIndex::new(index)];
-            match cell.category {
-                Category::OccurCell(_) => stream.write(&(cell.get_occurrence().unwrap() as u8)
-                    .to_string()
-                    .as_bytes())?,
-                Category::StaticCell(_) => stream.write(&(cell.get_value().unwrap() as u8)
-                    .to_string()
-                    .as_bytes())?,
-                _ => stream.write(&"P".as_bytes())?
-            };
-            if cell.coordinates.col < self.col_max {
-                stream.write(&";".as_bytes())?;
-            } else if cell.coordinates.col == self.col_max {
-                stream.write(&"\n".as_bytes())?;
-            }
+    pub fn write_view<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let mut stream = BufWriter::new(writer);
+
+        // Each row has as many values as in the data file, the padding is not printed
+        // A row gets longer when a cell is written beyond, and the rows in between are empty
+        let rows = self.widths.keys().next_back().map_or(0, |&row| row + 1);
+        for row in 0..rows {
+            self.write_row(&mut stream, row, 0, self.widths.get(&row).copied().unwrap_or(0))?;
         }
-        Ok(())
+        Ok(stream.flush()?)
     }
 
 
-    /// Spread the informations of a new value through the spreadsheet.
+    /// Write the cells of a rectangle in the "view.csv" format.
+    /// The rectangle is cut to the last row and column of the sheet first
     ///
     /// # Arguments
-    /// * 'cell' - Reference to the new cell.
-    /// * 'old_val' - Old value in the current coordinates of the new cell.
+    /// * 'rectangle' - The cells to write.
+    /// * 'writer' - Where the data are written.
     ///
     /// # Return value
     /// Nothing if everything was alright, else Error.
-    pub fn spread_information(&mut self, new_cell: &mut Cell,  old_cell_value: Option<u32>) -> Result<(), Error> {
-        // If the new value equals the old value, spread the information is useless
-        if new_cell.get_special() == old_cell_value {
-            return Ok(());
+    pub(crate) fn write_area<W: Write>(&self, rectangle: &Rectangle, writer: W) -> Result<(), Error> {
+        let mut stream = BufWriter::new(writer);
+        if rectangle.is_valid() && !self.widths.is_empty() {
+            let last_row = rectangle.end.row.min(self.row_max);
+            let last_col = rectangle.end.col.min(self.col_max);
+            for row in rectangle.begin.row..=last_row {
+                if rectangle.begin.col <= last_col {
+                    self.write_row(&mut stream, row, rectangle.begin.col, last_col + 1)?;
+                }
+            }
         }
+        Ok(stream.flush()?)
+    }
 
-        // Insert the new value in the changes collection
-        // All changes are going to be written in a file
-        self.changes.insert(new_cell.coordinates, new_cell.get_value_string());
-
-
-        // The changes variable stores all cells affected by the new value
-        let mut local_changes: Vec<(NodeIndex<u32>, Option<u32>)> = Vec::new();
-
-        let cloned_graph = self.graph.clone();
-
-        // Browse all cell's parents
-        // If the new value affect one on them, add it into changes
-        for father in cloned_graph.neighbors_directed(new_cell.get_index(), Incoming) {
-            // Instead of looking for the parent value, look for the "v" value
-            // The v value is the occurrence value sought, in (r1, c1, r2, c2, v)
-            // Here the v value is called ref_occur
-            let mut father_cell = self.graph[father];
-            //println!("A FATHER is {:?}",father_cell.clone());
-            let father_value = father_cell.get_value();
-
-            // If the new cell is a None all of it fathers should be to
-            if new_cell.get_special() == None {
-
-                let value = self.graph[father].get_special();
-                local_changes.push((father, value));
-                self.graph[father].set_category(Category::FaultyCell);
+    /// Write the results of a row from the first column to the end one, excluded
+    /// In JSON, a row is an array of results
+    fn write_row<W: Write>(&self, stream: &mut W, row: u32, first: u32, end: u32) -> Result<(), Error> {
+        let (open, separator, close) = match self.format {
+            Format::Text => ("", ";", ""),
+            Format::Json => ("[", ",", "]"),
+        };
+        stream.write_all(open.as_bytes())?;
+        for col in first..end {
+            let result = self.grid.get_result(&Coordinates::from(row, col));
+            stream.write_all(self.result_string(result).as_bytes())?;
+            if col + 1 < end {
+                stream.write_all(separator.as_bytes())?;
+            }
+        }
+        stream.write_all(close.as_bytes())?;
+        Ok(stream.write_all("\n".as_bytes())?)
+    }
 
-            } else if new_cell.get_special() == father_value {
+    /// Update a cell from the old and new values of the children that changed
+    /// The cell ends up as if it was evaluated again from all of its children
+    fn update_node(&mut self, coordinates: Coordinates, changes: &[Change]) {
+        // A static cell is not updated, it has nothing to read
+        let category = match self.grid.get_other(&coordinates) {
+            Some(category) => category,
+            None => return,
+        };
+        match *category {
+            // A father faulty because of its children gets its formula back, it may still be faulty
+            // Any other faulty father stays faulty
+            Category::FaultyCell(CellError::Propagated) => { self.restore_source(coordinates); }
+            Category::FaultyCell(_) | Category::StaticCell(_) => {}
+
+            // An expression can't be updated from the old values only
+            // It is evaluated again from the current values of its children
+            Category::ExprCell(_) => self.evaluate_node(coordinates),
+
+            // Each function has it own rule to follow the change of a child
+            Category::AggregateCell(_) => self.update_aggregate(coordinates, changes),
+
+            Category::OccurCell(occ) => {
+                // With the Propagate policy, a faulty child makes the father faulty too
+                // A child with no value may also have become empty, the father is evaluated again to know it
+                if self.propagation == Propagation::Propagate && changes.iter().any(|(_, new)| new.is_none()) {
+                    self.evaluate_node(coordinates);
+                    return;
+                }
 
-                // If the new cell equals the ref_occur value, it's a new occurence
-                // The parent's value need to be incremented
-                let value = self.graph[father].get_special();
-                local_changes.push((father, value));
-                self.graph[father].increment_occ();
+                // Instead of looking for the children values, look for the predicate
+                // A child crossing the predicate boundary inward is a new occurrence,
+                // a child crossing it outward is not an occurrence anymore
+                let mut occurrence = occ.occurrence;
+                for &(old, new) in changes {
+                    match (occ.predicate.matches(old), occ.predicate.matches(new)) {
+                        (false, true) => occurrence += 1,
+                        (true, false) => occurrence -= 1,
+                        _ => {}
+                    }
+                }
+                self.grid.set_category(coordinates, Category::OccurCell(OccurCell { occurrence, ..occ }));
+            }
+        }
+    }
 
-            } else if old_cell_value == father_value {
+    /// Record that a cell went from an old result to its current one, None for an empty cell
+    /// The change is written in the changes collection,
+    /// and given to every father of the cell if its value changed
+    /// An empty cell has no value, but it is not faulty: a change from or to it is always given
+    fn record_change(&mut self, coordinates: Coordinates, old: Option<Result<u32, CellError>>,
+                     inputs: &mut HashMap<Coordinates, Vec<Change>>) {
+        let new = self.grid.get_result(&coordinates);
+        if new == old {
+            return;
+        }
+        let (old_value, new_value) = (old.and_then(Result::ok), new.and_then(Result::ok));
+        let emptied_or_filled = old.is_none() || new.is_none();
 
-                // If the new cell is different, but the old value was equal to new value
-                // It was an occurence, but now it's not
-                // The parent's value need to be decremented
-                let value = self.graph[father].get_special();
-                local_changes.push((father, value));
-                self.graph[father].decrement_occ();
+        // All changes are going to be written in a file
+        // A faulty cell staying faulty only changes when the kind of error is printed
+        if self.show_errors || old_value.is_some() || new_value.is_some() || emptied_or_filled {
+            self.changes.insert(coordinates, new);
+        }
+        if old_value != new_value || emptied_or_filled {
+            if let Some(occurrences) = &mut self.occurrences {
+                if old.is_some() {
+                    occurrences.remove(&coordinates, old_value);
+                }
+                if new.is_some() {
+                    occurrences.add(&coordinates, new_value);
+                }
+            }
+            for father in self.dependencies.fathers(&coordinates) {
+                inputs.entry(father).or_default().push((old_value, new_value));
+            }
+        }
+    }
 
+    /// Spread the informations of new values through the spreadsheet.
+    ///
+    /// Every cell reading a changed cell, directly or not, is visited once in topological order:
+    /// after all the cells it reads, so it never goes through intermediate states.
+    /// A visited cell is updated only if one of its children did change.
+    /// The result is the same as evaluating all the spreadsheet again.
+    ///
+    /// # Arguments
+    /// * 'seeds' - The cells which already changed, with their old result.
+    /// * 'restored' - The cells getting their formula back, evaluated when visited.
+    fn spread_information(&mut self, seeds: Vec<(Coordinates, Option<Result<u32, CellError>>)>,
+                          restored: BTreeSet<Coordinates>) {
+        let mut starts: Vec<Coordinates> = seeds.iter().map(|&(coordinates, _)| coordinates).collect();
+        starts.extend(&restored);
+
+        // A cell on a cycle stays faulty whatever its children are
+        // Without them, the cells to visit have no cycle
+        let mut affected = self.get_readers(&starts);
+        affected.extend(&restored);
+        affected.retain(|coordinates| {
+            restored.contains(coordinates) || self.grid.get_error(coordinates) != Some(CellError::Cycle)
+        });
+
+        // Count for each affected cell how many affected cells it reads
+        let mut waiting: HashMap<Coordinates, usize> = affected.iter()
+            .map(|&coordinates| (coordinates, 0))
+            .collect();
+        for coordinates in &affected {
+            for father in self.dependencies.fathers(coordinates) {
+                if let Some(count) = waiting.get_mut(&father) {
+                    *count += 1;
+                }
             }
         }
 
-        // Browse all changed parents and spread information for each one
-        for change in local_changes {
-            // here we recall with the father and
+        // The old and new values of the changed children of each cell
+        let mut inputs = HashMap::new();
+        for (coordinates, old) in seeds {
+            self.record_change(coordinates, old, &mut inputs);
+        }
 
-            let mut father_cell = self.graph[change.0];
-            self.spread_information( &mut father_cell, change.1)?;
+        // A cell is visited once all the affected cells it reads are
+        let mut ready: Vec<Coordinates> = waiting.iter()
+            .filter(|(_, &count)| count == 0)
+            .map(|(&coordinates, _)| coordinates)
+            .collect();
+        while let Some(coordinates) = ready.pop() {
+            let old = self.grid.get_result(&coordinates);
+            if restored.contains(&coordinates) {
+                self.restore_source(coordinates);
+            } else if let Some(changes) = inputs.remove(&coordinates) {
+                self.update_node(coordinates, &changes);
+            }
+            self.record_change(coordinates, old, &mut inputs);
+
+            for father in self.dependencies.fathers(&coordinates) {
+                if let Some(count) = waiting.get_mut(&father) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(father);
+                    }
+                }
+            }
         }
 
-        Ok(())
     }
 
     /// Insert a new cell into the spreadsheet.
     /// Then, spread the information of this new arrival.
     ///
     /// # Arguments
-    /// * 'cell' - Reference to the new cell.
-    /// * 'crd' - A tuple reference to the cell coordinates.
-    ///
-    /// # Return value
-    /// Nothing if everything was alright, else Error.
-    pub fn insert_spread_cell(&mut self, mut new_cell: Cell) -> Result<(), Error> {
-
-        // Index of the node that will store the new cell
-        let cell_index = self.get_index_node_from_crd(&new_cell.coordinates);
-        let mut old_cell = self.graph[cell_index];
-
-        // Add the cell into the node to erase the old one
-        self.graph[cell_index].set_category(new_cell.category);
-        self.graph[cell_index].set_index(old_cell.get_index());
-        new_cell.set_index(cell_index);
-
-        //println!("new cell : {:?}",new_cell);
-        // Delete all outgoing edges from old node
-        for child_index in self.graph.clone().neighbors(cell_index) {
-            let edge = self.graph.find_edge(cell_index,child_index);
-            if let Some(edge_index) = edge {
-                self.graph.remove_edge(edge_index);
+    /// * 'cell_index' - Coordinates of the cell to replace.
+    /// * 'new_cell' - The new cell, None to empty it.
+    fn insert_spread_cell(&mut self, cell_index: Coordinates, new_cell: Option<Cell>) {
+
+        // The cells changed by the insert, with their old value
+        let mut seeds = vec![(cell_index, self.grid.get_result(&cell_index))];
+
+        // Cells on the cycle closed by the new cell, if any
+        let mut new_cycle = BTreeSet::new();
+
+        // Cells sharing a cycle with the old cell, the new one may break it
+        let old_cycle = self.get_cycle(cell_index).unwrap_or_default();
+
+        // Add the cell into the grid to erase the old one
+        // The sheet grows if the cell is beyond its last row or column, and so does the row of the view
+        // An emptied cell leaves the sheet as it is
+        match &new_cell {
+            Some(new_cell) => {
+                self.grid.set_category(cell_index, new_cell.category.clone());
+                self.row_max = self.row_max.max(cell_index.row);
+                self.col_max = self.col_max.max(cell_index.col);
+                let width = self.widths.entry(cell_index.row).or_insert(0);
+                *width = (*width).max(cell_index.col + 1);
             }
+            None => self.grid.remove(&cell_index),
         }
 
-        // If the new cell is a dynamic one, we have to add it into eval
-        // Then check if it create a cycle
-        if let Category::OccurCell(_) = new_cell.category {
+        //println!("new cell : {:?}",new_cell);
+        // The old cell does not read anything anymore
+        self.dependencies.remove(cell_index);
+
+        // If the new cell is a dynamic one, check if it create a cycle
+        if let Some(new_cell) = new_cell.filter(Cell::is_formula) {
 
-            self.evals.insert(new_cell.coordinates,new_cell);
-            self.link_nodes();
+            // Only the new cell is linked, the other dependencies did not change
+            // The new cell closes a cycle only if it reads one of its readers
+            self.link_node(&new_cell);
 
             // Handling new inserted cycle by user
-            let mut cloned_graph = self.graph.clone();
-            let mut cell_to_update = self.check_cycle(&mut cloned_graph);
+            if let Some(cycle) = self.get_cycle(cell_index) {
+                // Every other cell of the cycle was valid until now
+                // Their fathers have to know they are becoming faulty
+                seeds.extend(cycle.iter()
+                    .filter(|&&index| index != cell_index)
+                    .map(|&index| (index, self.grid.get_result(&index))));
 
-            if let Some(cycle) = cell_to_update {
-                let mut tmp_vec = Vec::new();
-                tmp_vec.push(new_cell.get_index());
                 // Update cell that have to be changed into FaultyCell
-                self.update_cells(tmp_vec);
+                new_cycle.extend(&cycle);
+                self.update_cells(cycle);
             } else {
                 // Evaluate the new cell
-                self.evaluate_cell(&new_cell.coordinates);
+                self.evaluate_node(new_cell.coordinates);
             }
 
         }
 
-        // Spread the information of this new presence
-        let mut cloned_cell = self.graph[new_cell.get_index()].clone();
-        self.spread_information(&mut cloned_cell,  old_cell.get_special())?;
+        // Cells of the old cycle which are not on a cycle anymore get their formula back
+        // A cycle left among them, other than the new one, was already there
+        // So it only goes through cells of the old cycle
+        let still_cycle: BTreeSet<Coordinates> = self.find_cycles_among(&old_cycle)
+            .into_iter()
+            .chain(new_cycle)
+            .collect();
+        let broken: BTreeSet<Coordinates> = old_cycle.into_iter()
+            .filter(|&index| index != cell_index && !still_cycle.contains(&index))
+            .filter(|index| self.grid.get_error(index) == Some(CellError::Cycle))
+            .collect();
+
+        // Spread the information of this new presence, in a single pass
+        self.spread_information(seeds, broken);
+    }
 
-        Ok(())
+    /// Give back its source formula to a faulty cell, then evaluate it
+    /// Return false if the cell has no formula to give back
+    fn restore_source(&mut self, coordinates: Coordinates) -> bool {
+        match self.sources.get(&coordinates) {
+            Some(datum) => {
+                let category = Cell::from_datum(datum).checked();
+                self.grid.set_category(coordinates, category);
+                self.evaluate_node(coordinates);
+                true
+            }
+            None => false,
+        }
     }
 
-    /// Browse a "user.txt" file.
-    /// Read an change, then apply it immediatly on the spreadsheet.
-    /// Repeat until there is no more changes.
+    /// Apply a change of the user, then spread it through the spreadsheet.
+    /// The cells it changed are added to the changes collection.
     ///
     /// # Arguments
-    /// * 'in_path' - File path where all user changes are written
-    /// * 'out_path' - File path where all spreadsheet changes are written
+    /// * 'command' - The coordinates of the cell and its new datum.
     ///
     /// # Return value
-    /// Nothing if everything was alright, else Error.
-    pub fn browse_user(&mut self, in_path: &String, out_path: &String) -> Result<(), Error> {
+    /// Nothing if the change was applied, else OutOfBounds if the sheet can't hold the cell.
+    pub(crate) fn apply(&mut self, command: &Command) -> Result<(), Error> {
 
-        let stream = BufReader::new(File::open(in_path)?);
-        let rule = Rules::new();
-        // Browse and parse the file
-        for line in stream.lines() {
+        // A cell the sheet can't hold is rejected before anything is changed
+        let coordinates = command.coordinates;
+        if coordinates.row > COORDINATE_MAX || coordinates.col > COORDINATE_MAX {
+            return Err(Error::OutOfBounds { file: None, line: None, coordinates });
+        }
 
+        // Creating the new cell to insert
+        let mut new_cell = Cell::new();
+        let new_category = Cell::from_datum(&command.datum);
+        self.set_source(command.coordinates, &command.datum);
 
-            // Extracting 'c', 'r' and 'd' from the current line
-            let unwrapped_line = &line.unwrap();
-            let cap = rule.user_cmd_regex.captures(unwrapped_line).unwrap();
-            let row: u32 = cap.get(1).unwrap().as_str().parse().unwrap();
-            let column: u32 = cap.get(2).unwrap().as_str().parse().unwrap();
-            let datum = cap.get(3).unwrap().as_str().to_string();
+        new_cell.set_category(new_category);
+        new_cell.set_coordinates(command.coordinates);
 
-            // Creating the new cell to insert
-            let coordinates = Coordinates::from(row,column);
-            let mut new_cell = Cell::new();
-            let new_category = Cell::from_formula(&datum.to_string(), &rule);
+        // Insert the new cell in the spreadsheet, then spread the information
+        self.insert_spread_cell(coordinates, Some(new_cell));
+        Ok(())
+    }
 
-            new_cell.set_category(new_category);
-            new_cell.set_coordinates(coordinates);
+    /// Empty a cell, then spread it through the spreadsheet.
+    /// The cells it changed are added to the changes collection.
+    ///
+    /// # Arguments
+    /// * 'coordinates' - The cell to empty.
+    ///
+    /// # Return value
+    /// Nothing if the cell was emptied, else OutOfBounds if the sheet can't hold the cell.
+    pub fn clear(&mut self, coordinates: Coordinates) -> Result<(), Error> {
 
-            // Insert the new cell in the spreadsheet, then spread the information
-            self.insert_spread_cell(new_cell).unwrap();
+        // A cell the sheet can't hold is rejected before anything is changed
+        if coordinates.row > COORDINATE_MAX || coordinates.col > COORDINATE_MAX {
+            return Err(Error::OutOfBounds { file: None, line: None, coordinates });
+        }
+        self.sources.remove(&coordinates);
+        self.evals.remove(&coordinates);
+        self.insert_spread_cell(coordinates, None);
+        Ok(())
+    }
 
-            // Print the all the changes
-            self.print_changes(unwrapped_line, out_path)?;
-            self.changes.clear();
+    /// Keep what a cell holds and the shape of the sheet, before the cell is changed
+    pub(crate) fn snapshot(&self, coordinates: Coordinates) -> Snapshot {
+        let content = match (self.sources.get(&coordinates), self.grid.get_category(&coordinates)) {
+            (Some(datum), _) => Content::Datum(datum.clone()),
+            (None, Some(Category::StaticCell(cell))) => Content::Datum(Datum::Value(cell.value)),
+            (None, Some(category)) => Content::Faulty(category),
+            (None, None) => Content::Empty,
+        };
+        Snapshot {
+            coordinates,
+            content,
+            width: self.widths.get(&coordinates.row).copied(),
+            row_max: self.row_max,
+            col_max: self.col_max,
+        }
+    }
 
+    /// Give back to a cell what it held, then spread it through the spreadsheet.
+    /// The sheet gets back its shape, so a cell written beyond the view leaves it as it was.
+    /// The cells it changed are added to the changes collection.
+    ///
+    /// # Arguments
+    /// * 'snapshot' - The cell and the shape of the sheet before the change.
+    ///
+    /// # Return value
+    /// Nothing if the cell was given back, else OutOfBounds if the sheet can't hold the cell.
+    pub(crate) fn restore(&mut self, snapshot: Snapshot) -> Result<(), Error> {
+        let coordinates = snapshot.coordinates;
+        match snapshot.content {
+            Content::Datum(datum) => self.apply(&Command { coordinates, datum })?,
+            Content::Faulty(category) => {
+                self.sources.remove(&coordinates);
+                let mut cell = Cell::new();
+                cell.set_category(category);
+                cell.set_coordinates(coordinates);
+                self.insert_spread_cell(coordinates, Some(cell));
+            }
+            Content::Empty => self.clear(coordinates)?,
         }
+
+        match snapshot.width {
+            Some(width) => self.widths.insert(coordinates.row, width),
+            None => self.widths.remove(&coordinates.row),
+        };
+        self.row_max = snapshot.row_max;
+        self.col_max = snapshot.col_max;
         Ok(())
     }
 
-    /// Print the changes collection in a file.
+    /// Apply a change of the user written as a line of the "user.txt" format, like "r c d".
+    ///
+    /// # Return value
+    /// Nothing if the line was applied, else a Parse error without its file nor its line,
+    /// or OutOfBounds if the sheet can't hold the cell.
+    pub fn apply_line(&mut self, line: &str) -> Result<(), Error> {
+        let command = parser::parse_command(line)?;
+        self.apply(&command)
+    }
+
+    /// Return the value of a cell, or the reason why it has none, None for an empty cell
+    pub fn get_result(&self, coordinates: &Coordinates) -> Option<Result<u32, CellError>> {
+        self.grid.get_result(coordinates)
+    }
+
+    /// Give the changes made since they were last taken, in lexicographic order of the coordinates.
+    /// A cell emptied by the user has no result.
+    pub fn take_changes(&mut self) -> BTreeMap<Coordinates, Option<Result<u32, CellError>>> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Write changes in the "changes.txt" format.
     ///
     /// # Arguments
     /// * 'after' - Last user action.
-    /// * 'path' - Path to the file where the changes are written.
+    /// * 'changes' - The changes made by this action.
+    /// * 'writer' - Where the changes are written.
     ///
     /// # Return value
     /// Nothing if everything was alright, else Error.
-    pub fn print_changes(&self, after: &String, path: &String) -> Result<(), Error> {
-        // Open the file where all changes will be write
-        let mut stream = OpenOptions::new()
-            .append(true)
-            .write(true)
-            .create(true)
-            .open(path)?;
+    pub fn write_changes<W: Write>(&self, after: &str, changes: &BTreeMap<Coordinates, Option<Result<u32, CellError>>>,
+                                   writer: W) -> Result<(), Error> {
+        let mut stream = BufWriter::new(writer);
+
+        // In JSON, the user action and its changes are a single object
+        // {"after": "x y v", "changes": [{"cell": "x y", "value": v}, ...]}
+        if self.format == Format::Json {
+            let changes: Vec<String> = changes.iter()
+                .map(|(coordinates, &result)| format!("{{\"cell\":{},\"value\":{}}}",
+                                                       format::quote(&self.notation.format(coordinates)),
+                                                       self.result_string(result)))
+                .collect();
+            writeln!(stream, "{{\"after\":{},\"changes\":[{}]}}", format::quote(after), changes.join(","))?;
+            return Ok(stream.flush()?);
+        }
 
         // Print the user action before changes
         // The user action have the form "after "x y v":"
         // Where (x, y) are coordinates in the spreadsheet and v the new value
-        stream.write(String::from("after \"")
-            .add(after.as_ref())
-            .add(&"\":\n")
+        stream.write_all(String::from("after \"")
+            .add(after)
+            .add("\":\n")
             .as_bytes())?;
 
         // Browse the changes collection and print all changes in lexicographic order
         // All changes have the form "x y v"
         // Where (x, y) are coordinates in the spreadsheet and v the new value
-        for (coordinates, val) in self.changes.clone() {
-            stream.write(String::from(coordinates.row.to_string())
-                .add(&" ")
-                .add(&coordinates.col.to_string())
-                .add(&" ")
-                .add(&val.to_string())
-                .add(&"\n")
+        // With the A1 or R1C1 notation, "x y" is replaced by "B7" or "R7C2"
+        // An emptied cell has nothing after its coordinates
+        for (coordinates, &result) in changes {
+            stream.write_all(self.cell_string(coordinates, result)
+                .add("\n")
                 .as_bytes())?;
         }
 
-        Ok(())
+        Ok(stream.flush()?)
     }
 
-    pub fn handle_cycles(&mut self, cloned_graph: &mut Graph<Cell, Cell, Directed>, memo: &mut Vec<NodeIndex<u32>>){
-        let vec_index = self.check_cycle(cloned_graph);
-        match vec_index {
-            Some(vec) => {
-                memo.extend(vec.iter().cloned());
-                for elt in vec {
-                    cloned_graph.remove_node(elt);
-                }
-                self.handle_cycles(cloned_graph,memo);
-            },
-            None => ()
+    /// Write why a cell has its result: what it holds, the areas it reads,
+    /// the faulty cells it reads, the cells reading it, and its cycle if it is on one
+    ///
+    /// # Arguments
+    /// * 'coordinates' - The cell to explain.
+    /// * 'writer' - Where the explanation is written.
+    pub fn write_explanation<W: Write>(&self, coordinates: &Coordinates, writer: W) -> Result<(), Error> {
+        let mut stream = BufWriter::new(writer);
+
+        // A formula is written from its source, it is kept even when the cell became faulty
+        let content = match (self.sources.get(coordinates), self.grid.get_category(coordinates)) {
+            (Some(datum), _) => datum.to_string(),
+            (None, Some(Category::StaticCell(cell))) => cell.value.to_string(),
+            (None, Some(_)) => "invalid formula".to_string(),
+            (None, None) => "empty".to_string(),
+        };
+        let result = self.grid.get_result(coordinates);
+        let areas = self.dependencies.areas(*coordinates);
+        let faulty: Vec<Coordinates> = areas.iter()
+            .flat_map(|area| self.area_cells(area))
+            .filter(|child| self.grid.get_error(child).is_some())
+            .collect::<BTreeSet<Coordinates>>()
+            .into_iter()
+            .collect();
+        let readers = self.dependencies.fathers(coordinates);
+        let mut cycle = self.get_cycle(*coordinates);
+        if let Some(cycle) = &mut cycle {
+            cycle.sort();
         }
-    }
 
-    pub fn process(args: &Vec<String>)  -> Result<(), Error> {
-        if args.len() != 5 {
-            println!("ERROR - Wrong number of arguments");
-            return Ok(());
+        let cells = |cells: &[Coordinates]| -> Vec<String> {
+            cells.iter().map(|cell| self.notation.format(cell)).collect()
+        };
+        let areas: Vec<String> = areas.iter()
+            .map(|area| format!("{}:{}", self.notation.format(&area.begin), self.notation.format(&area.end)))
+            .collect();
+
+        match self.format {
+            Format::Text => {
+                // An empty list is written "none" rather than nothing
+                let list = |strings: Vec<String>| -> String {
+                    if strings.is_empty() { "none".to_string() } else { strings.join(", ") }
+                };
+                writeln!(stream, "cell: {}", self.notation.format(coordinates))?;
+                writeln!(stream, "content: {}", content)?;
+                writeln!(stream, "result: {}", self.result_string(result))?;
+                writeln!(stream, "reads: {}", list(areas))?;
+                writeln!(stream, "faulty cells read: {}", list(cells(&faulty)))?;
+                writeln!(stream, "read by: {}", list(cells(&readers)))?;
+                if let Some(cycle) = &cycle {
+                    writeln!(stream, "cycle: {}", list(cells(cycle)))?;
+                }
+            }
+            Format::Json => {
+                let list = |strings: Vec<String>| -> String {
+                    let quoted: Vec<String> = strings.iter().map(|string| format::quote(string)).collect();
+                    format!("[{}]", quoted.join(","))
+                };
+                writeln!(stream, "{{\"cell\":{},\"content\":{},\"result\":{},\"reads\":{},\"faulty\":{},\"read_by\":{},\"cycle\":{}}}",
+                         format::quote(&self.notation.format(coordinates)),
+                         format::quote(&content),
+                         self.result_string(result),
+                         list(areas),
+                         list(cells(&faulty)),
+                         list(cells(&readers)),
+                         cycle.map_or("null".to_string(), |cycle| list(cells(&cycle))))?;
+            }
         }
+        Ok(stream.flush()?)
+    }
 
-        /*if !(check::check_user_file(&args[2])?) {
-            println!("ERROR - User file format incrorrect");
-            return Ok(());
-        }*/
-        let _stream = OpenOptions::new()
-            .truncate(true)
-            .write(true)
-            .create(true)
-            .open(&args[4])?;
-
-        let mut sheet = SpreadSheet::new();
-
-        // Browse the data file and fill the main cells map
-        sheet.browse_data(&args[1]);
-
-        // link between the graph nodes
-        sheet.link_nodes();
-
-        // The idea is to clone the main graph
-        // Remove every node participating in a cycle from the cloned graph
-        // We gather index of the removed nodes
-        // We change them into FaultyCell in the original graph
-        // That way we are handling all cycles without removing anything from our main graph :)
-        let mut cloned_graph = sheet.graph.clone();
-        let mut cell_to_update = Vec::new();
-        sheet.handle_cycles(&mut cloned_graph, &mut cell_to_update);
-
-        // Update cells that have to be changed into FaultyCell
-        sheet.update_cells(cell_to_update);
-
-        // Evaluate all cells
-        sheet.evaluate_all();
-
-        // Print all cell from graph
-        sheet.print_view(&args[3]).unwrap();
-
-        //sheet.print_cells();
-
-        // Browser a file with changes, apply them on the spread sheet
-        sheet.browse_user(&args[2], &args[4])?;
+    /// Write the dependency graph: every formula, with the cells holding something it reads
+    /// As text, the graph is written in the DOT language, an edge going from a formula to a cell it reads
+    /// In JSON, each formula is an object {"cell": "x y", "reads": ["x y", ...]}
+    ///
+    /// # Arguments
+    /// * 'writer' - Where the graph is written.
+    pub fn write_graph<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let mut stream = BufWriter::new(writer);
 
-        Ok(())
+        let mut formulas: Vec<Coordinates> = self.dependencies.formulas().collect();
+        formulas.sort();
 
+        if self.format == Format::Text {
+            writeln!(stream, "digraph sheet {{")?;
+        }
+        for formula in formulas {
+            let children: BTreeSet<Coordinates> = self.dependencies.areas(formula).iter()
+                .flat_map(|area| self.area_cells(area))
+                .collect();
+            let father = format::quote(&self.notation.format(&formula));
+            let children = children.iter().map(|child| format::quote(&self.notation.format(child)));
+            match self.format {
+                Format::Text => {
+                    writeln!(stream, "    {};", father)?;
+                    for child in children {
+                        writeln!(stream, "    {} -> {};", father, child)?;
+                    }
+                }
+                Format::Json => {
+                    let children: Vec<String> = children.collect();
+                    writeln!(stream, "{{\"cell\":{},\"reads\":[{}]}}", father, children.join(","))?;
+                }
+            }
+        }
+        if self.format == Format::Text {
+            writeln!(stream, "}}")?;
+        }
+        Ok(stream.flush()?)
     }
-
-
 }
@@ -0,0 +1,274 @@
+//! Run the ws binary on small files and check its outputs and exit status.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const DATA: &str = "1;2\n=SUM(0,0,0,1);=#(0,0,0,1,2)\n";
+
+const USER: &str = "\
+0 0 5
+0 1 =#(0,0
+B1 7
+0 0 300
+0 0 3
+";
+
+/// A directory of its own for a test, holding these files
+fn directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    for (file, content) in files {
+        fs::write(directory.join(file), content).unwrap();
+    }
+    directory
+}
+
+/// Run ws in the directory with these arguments
+fn ws(directory: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ws"))
+        .current_dir(directory)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn read(directory: &Path, file: &str) -> String {
+    fs::read_to_string(directory.join(file)).unwrap()
+}
+
+/// Evaluate DATA and apply USER with these options
+fn eval(name: &str, options: &[&str]) -> (PathBuf, Output) {
+    let directory = directory(name, &[("data.csv", DATA), ("user.txt", USER)]);
+    let mut args = vec!["eval", "--data", "data.csv", "--user", "user.txt", "--view", "view.csv", "--changes", "changes.txt"];
+    args.extend(options);
+    let output = ws(&directory, &args);
+    (directory, output)
+}
+
+#[test]
+fn invalid_lines_abort_by_default() {
+    let (directory, output) = eval("invalid-abort", &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr),
+               "ERROR - user.txt, line 2, column 11: expected Comma, found end of input\n");
+    assert_eq!(read(&directory, "view.csv"), "1;2\n3;1\n");
+    // The lines before the invalid one are applied
+    assert_eq!(read(&directory, "changes.txt"), "after \"0 0 5\":\n0 0 5\n1 0 7\n");
+}
+
+#[test]
+fn skipped_lines_are_reported_and_left_out_of_the_changes() {
+    let (directory, output) = eval("invalid-skip", &["--invalid", "skip"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "\
+user.txt, line 2, column 11: expected Comma, found end of input
+user.txt, line 4, column 5: value must be between 0 and 255
+");
+    assert_eq!(read(&directory, "changes.txt"), "\
+after \"0 0 5\":
+0 0 5
+1 0 7
+after \"B1 7\":
+0 1 7
+1 0 12
+1 1 0
+after \"0 0 3\":
+0 0 3
+1 0 10
+");
+}
+
+#[test]
+fn recorded_lines_are_kept_in_the_changes_with_no_change() {
+    let (directory, output) = eval("invalid-record", &["--invalid", "record"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr).lines().count(), 2);
+    assert_eq!(read(&directory, "changes.txt"), "\
+after \"0 0 5\":
+0 0 5
+1 0 7
+after \"0 1 =#(0,0\":
+after \"B1 7\":
+0 1 7
+1 0 12
+1 1 0
+after \"0 0 300\":
+after \"0 0 3\":
+0 0 3
+1 0 10
+");
+}
+
+#[test]
+fn diagnostics_are_written_to_their_file() {
+    let (directory, output) = eval("invalid-diagnostics", &["--invalid", "skip", "--diagnostics", "diagnostics.txt"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    assert_eq!(read(&directory, "diagnostics.txt"), "\
+user.txt, line 2, column 11: expected Comma, found end of input
+user.txt, line 4, column 5: value must be between 0 and 255
+");
+}
+
+#[test]
+fn a_cell_beyond_the_sheet_is_an_invalid_line() {
+    let directory = directory("invalid-bounds", &[("data.csv", DATA), ("user.txt", "4294967295 0 1\n0 0 2\n")]);
+    let output = ws(&directory, &["apply", "--data", "data.csv", "--user", "user.txt", "--output", "view.csv",
+                                  "--invalid", "skip", "--diagnostics", "diagnostics.txt"]);
+    assert!(output.status.success());
+    assert_eq!(read(&directory, "diagnostics.txt"),
+               "user.txt, line 1, cell 4294967295 0 is beyond the last row or column\n");
+    assert_eq!(read(&directory, "view.csv"), "2;2\n4;2\n");
+}
+
+#[test]
+fn explain_and_graph_only_browse_the_cells_of_a_far_area() {
+    let directory = directory("far-area", &[
+        ("data.csv", "1;2\n=#(0,0,4000000000,4000000000,>0);3\n"),
+        ("user.txt", "4000000000 4000000000 5\n"),
+    ]);
+    let output = ws(&directory, &["explain", "--data", "data.csv", "--user", "user.txt", "--cell", "A2"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("faulty cells read: 1 0\n"));
+
+    let output = ws(&directory, &["graph", "--data", "data.csv", "--user", "user.txt"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("    \"1 0\" -> \"4000000000 4000000000\";\n}\n"));
+}
+
+#[test]
+fn check_reports_every_problem_with_its_place() {
+    let directory = directory("check", &[
+        ("data.csv", DATA), ("user.txt", "0 0 5\nB1 7\n"),
+        ("bad-data.csv", "1;=#(;3\n"), ("bad-user.txt", USER), ("bad-view.csv", "1;P;300\n"),
+    ]);
+    let output = ws(&directory, &["check", "--data", "data.csv", "--user", "user.txt"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let output = ws(&directory, &["check", "--data", "bad-data.csv", "--user", "bad-user.txt", "--view", "bad-view.csv"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+bad-data.csv, line 1, column 6: expected a number, found end of input
+bad-user.txt, line 2, column 11: expected Comma, found end of input
+bad-user.txt, line 4, column 5: value must be between 0 and 255
+bad-view.csv, line 1, column 5: expected a value or P, found \"300\"
+");
+
+    let output = ws(&directory, &["check", "--data", "bad-data.csv", "--format", "json"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout),
+               "{\"error\":\"bad-data.csv, line 1, column 6: expected a number, found end of input\"}\n");
+}
+
+#[test]
+fn explain_describes_a_cell_once_the_user_file_is_applied() {
+    let directory = directory("explain", &[("data.csv", DATA), ("user.txt", "0 0 =A2\n")]);
+    let output = ws(&directory, &["explain", "--data", "data.csv", "--user", "user.txt", "--cell", "1,0"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+cell: 1 0
+content: =SUM(0,0,0,1)
+result: P
+reads: 0 0:0 1
+faulty cells read: 0 0
+read by: 0 0
+cycle: 0 0, 1 0
+");
+
+    let output = ws(&directory, &["explain", "--data", "data.csv", "--cell", "B2", "--notation", "a1"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+cell: B2
+content: =#(0,0,0,1,2)
+result: 1
+reads: A1:B1
+faulty cells read: none
+read by: none
+");
+}
+
+#[test]
+fn explain_in_json() {
+    let directory = directory("explain-json", &[("data.csv", DATA), ("user.txt", "0 0 =A2\n")]);
+    let output = ws(&directory, &["explain", "--data", "data.csv", "--user", "user.txt", "--cell", "A2",
+                                  "--format", "json", "--errors"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "{\"cell\":\"1 0\",\"content\":\"=SUM(0,0,0,1)\",\
+\"result\":\"#CYCLE\",\"reads\":[\"0 0:0 1\"],\"faulty\":[\"0 0\"],\"read_by\":[\"0 0\"],\"cycle\":[\"0 0\",\"1 0\"]}\n");
+
+    let output = ws(&directory, &["explain", "--data", "data.csv", "--cell", "A1", "--format", "json"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "{\"cell\":\"0 0\",\"content\":\"1\",\
+\"result\":1,\"reads\":[],\"faulty\":[],\"read_by\":[\"1 0\",\"1 1\"],\"cycle\":null}\n");
+}
+
+#[test]
+fn graph_links_every_formula_to_the_cells_it_reads() {
+    let directory = directory("graph", &[("data.csv", DATA)]);
+    let output = ws(&directory, &["graph", "--data", "data.csv"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+digraph sheet {
+    \"1 0\";
+    \"1 0\" -> \"0 0\";
+    \"1 0\" -> \"0 1\";
+    \"1 1\";
+    \"1 1\" -> \"0 0\";
+    \"1 1\" -> \"0 1\";
+}
+");
+
+    let output = ws(&directory, &["graph", "--data", "data.csv", "--format", "json", "--notation", "a1"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+{\"cell\":\"A2\",\"reads\":[\"A1\",\"B1\"]}
+{\"cell\":\"B2\",\"reads\":[\"A1\",\"B1\"]}
+");
+}
+
+#[test]
+fn view_and_changes_in_json() {
+    let directory = directory("eval-json", &[("data.csv", "1;2;\n=1/0;=#(0,0,0,1,2)\n"), ("user.txt", "0 0 5\nA2 =B2\n")]);
+    let output = ws(&directory, &["eval", "--data", "data.csv", "--user", "user.txt", "--view", "view.json",
+                                  "--changes", "changes.json", "--format", "json", "--errors"]);
+    assert!(output.status.success());
+    assert_eq!(read(&directory, "view.json"), "[1,2,null]\n[\"#ARITH\",1]\n");
+    assert_eq!(read(&directory, "changes.json"), "\
+{\"after\":\"0 0 5\",\"changes\":[{\"cell\":\"0 0\",\"value\":5}]}
+{\"after\":\"A2 =B2\",\"changes\":[{\"cell\":\"1 0\",\"value\":1}]}
+");
+}
+
+#[test]
+fn the_command_line_of_the_specification_is_still_accepted() {
+    let directory = directory("legacy", &[("data.csv", DATA), ("user.txt", "0 0 5\nB1 7\n")]);
+    let output = ws(&directory, &["data.csv", "user.txt", "view.csv", "changes.txt"]);
+    assert!(output.status.success());
+    assert_eq!(read(&directory, "view.csv"), "1;2\n3;1\n");
+    assert_eq!(read(&directory, "changes.txt"), "\
+after \"0 0 5\":
+0 0 5
+1 0 7
+after \"B1 7\":
+0 1 7
+1 0 12
+1 1 0
+");
+
+    // Options follow the four files
+    let output = ws(&directory, &["data.csv", "user.txt", "view.csv", "changes.txt", "--notation", "a1"]);
+    assert!(output.status.success());
+    assert!(read(&directory, "changes.txt").starts_with("after \"0 0 5\":\nA1 5\nA2 7\n"));
+
+    // Less than four files is not the command line of the specification
+    let output = ws(&directory, &["data.csv", "user.txt"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unrecognized subcommand 'data.csv'"));
+}
+
+#[test]
+fn a_missing_file_is_an_error() {
+    let directory = directory("missing", &[]);
+    let output = ws(&directory, &["apply", "--data", "data.csv", "--user", "user.txt"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("ERROR - "));
+}
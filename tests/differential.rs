@@ -0,0 +1,197 @@
+//! Check that a sheet kept up to date change after change is the sheet evaluated again from scratch.
+//!
+//! Random sheets get random user commands: values, every kind of formula, cycles,
+//! invalid rectangles and areas going beyond the cells, and cells emptied again.
+//! After each command, the incremental results must be the results of a fresh evaluation,
+//! and the changes must be exactly the cells whose result changed.
+
+use defi2::{CellError, Coordinates, Propagation, SpreadSheet};
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+
+/// Rows and columns of the generated references, a bit beyond the generated sheets
+const SIZE: u32 = 7;
+
+type Results = BTreeMap<Coordinates, Result<u32, CellError>>;
+
+fn value() -> impl Strategy<Value = u32> {
+    prop_oneof![0..4u32, 0..=255u32]
+}
+
+fn reference() -> impl Strategy<Value = String> {
+    (0..SIZE, 0..SIZE).prop_map(|(row, col)| format!("{}{}", (b'A' + col as u8) as char, row + 1))
+}
+
+/// A rectangle may be reversed, which makes the formula faulty
+fn rectangle() -> impl Strategy<Value = String> {
+    (0..SIZE, 0..SIZE, 0..SIZE, 0..SIZE)
+        .prop_map(|(r1, c1, r2, c2)| format!("{},{},{},{}", r1, c1, r2, c2))
+}
+
+fn predicate() -> impl Strategy<Value = String> {
+    prop_oneof![
+        value().prop_map(|v| v.to_string()),
+        (prop_oneof![Just("<"), Just("<="), Just(">"), Just(">="), Just("!=")], value())
+            .prop_map(|(operator, v)| format!("{}{}", operator, v)),
+        (value(), value()).prop_map(|(a, b)| format!("[{}..{}]", a.min(b), a.max(b))),
+    ]
+}
+
+fn term() -> impl Strategy<Value = String> {
+    prop_oneof![
+        reference(),
+        value().prop_map(|v| v.to_string()),
+        (rectangle(), predicate()).prop_map(|(area, predicate)| format!("#({},{})", area, predicate)),
+        (prop_oneof![Just("SUM"), Just("MIN"), Just("MAX"), Just("AVERAGE")], rectangle())
+            .prop_map(|(function, area)| format!("{}({})", function, area)),
+    ]
+}
+
+fn formula() -> impl Strategy<Value = String> {
+    prop_oneof![
+        term().prop_map(|term| format!("={}", term)),
+        (term(), prop_oneof![Just('+'), Just('-'), Just('*'), Just('/')], term())
+            .prop_map(|(left, operator, right)| format!("={}{}{}", left, operator, right)),
+    ]
+}
+
+/// A datum the user can write
+fn datum() -> impl Strategy<Value = String> {
+    prop_oneof![2 => value().prop_map(|v| v.to_string()), 3 => formula()]
+}
+
+/// A value of the data file, which may also be empty or wrong
+fn data_value() -> impl Strategy<Value = String> {
+    prop_oneof![4 => datum(), 1 => Just(String::new()), 1 => Just("=#(".to_string())]
+}
+
+/// Rows of different lengths
+fn data() -> impl Strategy<Value = Vec<Vec<String>>> {
+    prop::collection::vec(prop::collection::vec(data_value(), 1..6), 1..6)
+}
+
+/// A command writes a datum in a cell, or empties it when there is none
+fn commands() -> impl Strategy<Value = Vec<(u32, u32, Option<String>)>> {
+    prop::collection::vec((0..SIZE, 0..SIZE, prop::option::weighted(0.8, datum())), 1..8)
+}
+
+fn new_sheet(propagation: Propagation, show_errors: bool, indexed: bool) -> SpreadSheet {
+    let mut sheet = SpreadSheet::new();
+    sheet.set_propagation(propagation);
+    sheet.set_show_errors(show_errors);
+    sheet.set_indexed(indexed);
+    sheet
+}
+
+/// Evaluate from scratch a sheet holding these data
+fn evaluate(data: &BTreeMap<(u32, u32), String>, propagation: Propagation) -> SpreadSheet {
+    let rows = data.keys().map(|&(row, _)| row + 1).max().unwrap_or(0);
+    let cols = data.keys().map(|&(_, col)| col + 1).max().unwrap_or(0);
+    let mut csv = String::new();
+    for row in 0..rows {
+        let line: Vec<&str> = (0..cols)
+            .map(|col| data.get(&(row, col)).map_or("", |datum| datum.as_str()))
+            .collect();
+        csv.push_str(&line.join(";"));
+        csv.push('\n');
+    }
+
+    let mut sheet = new_sheet(propagation, true, false);
+    sheet.read_data(csv.as_bytes()).unwrap();
+    sheet.evaluate();
+    sheet
+}
+
+/// The result of every cell which is not empty
+fn results(sheet: &SpreadSheet) -> Results {
+    let mut results = BTreeMap::new();
+    for row in 0..SIZE {
+        for col in 0..SIZE {
+            let coordinates = Coordinates::from(row, col);
+            if let Some(result) = sheet.get_result(&coordinates) {
+                results.insert(coordinates, result);
+            }
+        }
+    }
+    results
+}
+
+/// The changes expected between two states of the sheet, None for an emptied cell
+/// A faulty cell staying faulty only changes when the kind of error is printed,
+/// a cell filled or emptied by a command always changes
+fn expected_changes(before: &Results, after: &Results, show_errors: bool) -> BTreeMap<Coordinates, Option<Result<u32, CellError>>> {
+    before.keys().chain(after.keys())
+        .filter_map(|coordinates| {
+            let (old, new) = (before.get(coordinates).copied(), after.get(coordinates).copied());
+            let changed = match (old, new) {
+                (Some(old), Some(new)) => old != new && (show_errors || old.is_ok() || new.is_ok()),
+                (old, new) => old != new,
+            };
+            changed.then_some((*coordinates, new))
+        })
+        .collect()
+}
+
+fn check(data: Vec<Vec<String>>, commands: Vec<(u32, u32, Option<String>)>,
+         propagation: Propagation, show_errors: bool, indexed: bool) -> Result<(), TestCaseError> {
+    let mut sources = BTreeMap::new();
+    for (row, values) in data.iter().enumerate() {
+        for (col, value) in values.iter().enumerate() {
+            if !value.is_empty() {
+                sources.insert((row as u32, col as u32), value.clone());
+            }
+        }
+    }
+    let csv: String = data.iter().map(|values| values.join(";") + "\n").collect();
+
+    let mut sheet = new_sheet(propagation, show_errors, indexed);
+    sheet.read_data(csv.as_bytes()).unwrap();
+    sheet.evaluate();
+    let mut before = results(&evaluate(&sources, propagation));
+    prop_assert_eq!(&results(&sheet), &before);
+
+    for (row, col, datum) in commands {
+        let line = match datum {
+            Some(datum) => {
+                let line = format!("{} {} {}", row, col, datum);
+                sheet.apply_line(&line).unwrap();
+                sources.insert((row, col), datum);
+                line
+            }
+            None => {
+                sheet.clear(Coordinates::from(row, col)).unwrap();
+                sources.remove(&(row, col));
+                format!("clear {} {}", row, col)
+            }
+        };
+
+        let after = results(&evaluate(&sources, propagation));
+        prop_assert_eq!(&results(&sheet), &after, "after {:?}", line);
+        prop_assert_eq!(sheet.take_changes(), expected_changes(&before, &after, show_errors),
+                        "changes after {:?}", line);
+        before = after;
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn incremental_matches_full_evaluation(data in data(), commands in commands()) {
+        check(data, commands, Propagation::Propagate, false, false)?;
+    }
+
+    #[test]
+    fn incremental_matches_full_evaluation_with_errors(data in data(), commands in commands()) {
+        check(data, commands, Propagation::Propagate, true, false)?;
+    }
+
+    #[test]
+    fn incremental_matches_full_evaluation_skipping_errors(data in data(), commands in commands()) {
+        check(data, commands, Propagation::Skip, true, false)?;
+    }
+
+    #[test]
+    fn incremental_matches_full_evaluation_with_index(data in data(), commands in commands()) {
+        check(data, commands, Propagation::Propagate, true, true)?;
+    }
+}
@@ -0,0 +1 @@
+./ws data.csv user.txt view.csv changes.txt --errors
@@ -0,0 +1,45 @@
+//! Run the seeds of the fuzz corpora through the checks of their fuzz targets.
+//!
+//! The seeds nest formulas far deeper than the parser accepts:
+//! they must end up as faulty cells instead of overflowing the stack.
+
+use defi2::{CellError, Coordinates, Error, SpreadSheet};
+use std::fs;
+use std::path::Path;
+
+/// Every file of a corpus of the fuzz directory, in name order
+fn corpus(target: &str) -> Vec<(String, Vec<u8>)> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus").join(target);
+    let mut seeds: Vec<(String, Vec<u8>)> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .map(|path| (path.display().to_string(), fs::read(&path).unwrap()))
+        .collect();
+    seeds.sort();
+    seeds
+}
+
+#[test]
+fn data_seeds_are_evaluated() {
+    for (path, data) in corpus("data") {
+        let mut sheet = SpreadSheet::new();
+        sheet.read_data(data.as_slice()).unwrap();
+        sheet.evaluate();
+        sheet.write_view(std::io::sink()).unwrap();
+        assert_eq!(sheet.get_error(&Coordinates::from(0, 1)), Some(CellError::Syntax), "{}", path);
+    }
+}
+
+#[test]
+fn formula_seeds_are_read_and_written_alike() {
+    for (path, content) in corpus("formula") {
+        let content = String::from_utf8(content).unwrap();
+        let mut sheet = SpreadSheet::new();
+        sheet.read_data(content.as_bytes()).unwrap();
+        sheet.evaluate();
+        assert_eq!(sheet.get_error(&Coordinates::from(0, 0)), Some(CellError::Syntax), "{}", path);
+
+        let mut written = SpreadSheet::new();
+        assert!(matches!(written.apply_line(&format!("0 0 {}", content)), Err(Error::Parse { .. })), "{}", path);
+    }
+}
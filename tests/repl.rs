@@ -0,0 +1,103 @@
+//! Run a session of the interactive mode over a small sheet and compare its output.
+
+use defi2::{Coordinates, Repl, SpreadSheet};
+
+/// Run the commands over the sheet, and return the session and everything written
+fn run(data: &str, commands: &str) -> (Repl, String) {
+    let mut sheet = SpreadSheet::new();
+    sheet.read_data(data.as_bytes()).unwrap();
+    sheet.evaluate();
+
+    let mut repl = Repl::new(sheet);
+    let mut output = Vec::new();
+    repl.run(commands.as_bytes(), &mut output, "").unwrap();
+    (repl, String::from_utf8(output).unwrap())
+}
+
+/// Run the commands over the sheet, and return everything written
+fn session(data: &str, commands: &str) -> String {
+    run(data, commands).1
+}
+
+/// The view of the sheet left by a session
+fn view(repl: &Repl) -> String {
+    let mut view = Vec::new();
+    repl.sheet().write_view(&mut view).unwrap();
+    String::from_utf8(view).unwrap()
+}
+
+#[test]
+fn edits_are_printed_and_undone() {
+    let output = session("1;2\n=SUM(0,0,0,1);\n", "\
+get 1 0
+0 0 5
+B2 =A2*2
+show A1 B2
+undo
+get B2
+undo
+show 0 0 1 1
+undo
+");
+    assert_eq!(output, "\
+1 0 3
+after \"0 0 5\":
+0 0 5
+1 0 7
+after \"B2 =A2*2\":
+1 1 14
+5;2
+7;14
+after \"undo\":
+1 1
+1 1
+after \"undo\":
+0 0 1
+1 0 3
+1;2
+3;
+nothing to undo
+");
+}
+
+#[test]
+fn wrong_commands_are_reported_and_the_session_goes_on() {
+    let output = session("1\n", "0 0 =#(\nget 0\nshow 1 1 0 0\nquit\n0 0 2\n");
+    assert_eq!(output, "\
+ERROR - column 8: expected a number, found end of input
+ERROR - column 5: expected a cell, as r c or A1
+ERROR - column 6: the first cell must be above and left of the second one
+");
+}
+
+#[test]
+fn undo_gives_back_the_shape_of_a_grown_sheet() {
+    let (repl, output) = run("1;2\n3;4\n", "3 2 7\nshow A1 C4\nundo\nshow A1 C4\n");
+    assert_eq!(output, "\
+after \"3 2 7\":
+3 2 7
+1;2;
+3;4;
+;;
+;;7
+after \"undo\":
+3 2
+1;2
+3;4
+");
+    assert_eq!(view(&repl), "1;2\n3;4\n");
+}
+
+#[test]
+fn a_cell_beyond_the_sheet_is_rejected_without_being_kept() {
+    let (repl, output) = run("1\n", "4294967295 0 =A1\nundo\n");
+    assert_eq!(output, "\
+ERROR - cell 4294967295 0 is beyond the last row or column
+nothing to undo
+");
+    assert_eq!(view(&repl), "1\n");
+
+    let mut explanation = Vec::new();
+    repl.sheet().write_explanation(&Coordinates::from(4294967295, 0), &mut explanation).unwrap();
+    assert!(String::from_utf8(explanation).unwrap().contains("content: empty\n"));
+}
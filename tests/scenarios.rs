@@ -0,0 +1,136 @@
+//! Run every scenario of the tests directory in-process and compare the outputs with the expected ones.
+//!
+//! A scenario is a directory holding "data.csv", "user.txt" (which may be empty),
+//! "expected-view.csv" and "expected-changes.txt".
+//! An optional "expected-final-view.csv" is the view once the user file is applied.
+//! An optional "options.txt" holds options of the command line, one per line, like "--errors".
+//! With UPDATE_EXPECTED=1 the expected files are written from the outputs instead.
+
+use defi2::{parse_value, Notation, Propagation, SpreadSheet};
+use std::env;
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// Every directory of the tests directory holding a data file, in name order
+fn scenarios() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut scenarios: Vec<PathBuf> = fs::read_dir(root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.join("data.csv").is_file())
+        .collect();
+    scenarios.sort();
+    scenarios
+}
+
+/// Read a file of a scenario, the error names the file
+fn read(scenario: &Path, name: &str) -> Result<String, String> {
+    let path = scenario.join(name);
+    fs::read_to_string(&path).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+/// Set the options of "options.txt" on the sheet
+fn configure(sheet: &mut SpreadSheet, scenario: &Path) -> Result<(), String> {
+    if !scenario.join("options.txt").is_file() {
+        return Ok(());
+    }
+    for option in read(scenario, "options.txt")?.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let (name, value) = option.split_once('=').unwrap_or((option, ""));
+        let known = match name {
+            "--errors" => { sheet.set_show_errors(true); true }
+            "--occurrence-index" => { sheet.set_indexed(true); true }
+            "--propagation" => Propagation::from_name(value).map(|propagation| sheet.set_propagation(propagation)).is_some(),
+            "--notation" => Notation::from_name(value).map(|notation| sheet.set_notation(notation)).is_some(),
+            "--padding" => parse_value(value).map(|padding| sheet.set_padding(Some(padding))).is_ok(),
+            _ => false,
+        };
+        if !known {
+            return Err(format!("{}: unknown option {:?}", scenario.join("options.txt").display(), option));
+        }
+    }
+    Ok(())
+}
+
+/// Evaluate the data of a scenario, apply its user file, and return the view, the changes
+/// and the view once the user file is applied
+/// A line of the user file which can't be applied is an error naming the scenario and the line
+fn run(scenario: &Path) -> Result<(String, String, String), String> {
+    let mut sheet = SpreadSheet::new();
+    configure(&mut sheet, scenario)?;
+    sheet.read_data(read(scenario, "data.csv")?.as_bytes())
+        .map_err(|error| format!("{}: {}", scenario.join("data.csv").display(), error))?;
+    sheet.evaluate();
+
+    let mut view = Vec::new();
+    sheet.write_view(&mut view).unwrap();
+
+    let mut changes = Vec::new();
+    for (number, line) in read(scenario, "user.txt")?.as_bytes().lines().enumerate() {
+        let line = line.unwrap();
+        sheet.apply_line(&line)
+            .map_err(|error| format!("{}, line {}: {}", scenario.join("user.txt").display(), number + 1, error))?;
+        let changed = sheet.take_changes();
+        sheet.write_changes(&line, &changed, &mut changes).unwrap();
+    }
+
+    let mut final_view = Vec::new();
+    sheet.write_view(&mut final_view).unwrap();
+
+    Ok((String::from_utf8(view).unwrap(), String::from_utf8(changes).unwrap(), String::from_utf8(final_view).unwrap()))
+}
+
+/// Line by line difference, "-" for an expected line and "+" for the line found instead
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut diff = String::new();
+    for index in 0..expected.len().max(actual.len()) {
+        let (old, new) = (expected.get(index), actual.get(index));
+        if old != new {
+            if let Some(old) = old {
+                diff.push_str(&format!("  {:>4} - {}\n", index + 1, old));
+            }
+            if let Some(new) = new {
+                diff.push_str(&format!("  {:>4} + {}\n", index + 1, new));
+            }
+        }
+    }
+    diff
+}
+
+#[test]
+fn scenarios_match_expected_files() {
+    let update = env::var("UPDATE_EXPECTED").is_ok_and(|value| value == "1");
+    let mut failures = Vec::new();
+
+    for scenario in scenarios() {
+        let (view, changes, final_view) = match run(&scenario) {
+            Ok(outputs) => outputs,
+            Err(error) => {
+                failures.push(format!("{}\n", error));
+                continue;
+            }
+        };
+        let outputs = [("expected-view.csv", &view), ("expected-changes.txt", &changes), ("expected-final-view.csv", &final_view)];
+        for (name, actual) in outputs {
+            let path = scenario.join(name);
+            // The final view is only checked by the scenarios asking for it
+            if name == "expected-final-view.csv" && !path.is_file() {
+                continue;
+            }
+            if update {
+                fs::write(&path, actual).unwrap();
+                continue;
+            }
+            match read(&scenario, name) {
+                Ok(expected) if &expected == actual => {}
+                Ok(expected) => failures.push(format!("{}:\n{}", path.display(), diff(&expected, actual))),
+                Err(error) => failures.push(format!("{}\n", error)),
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "{} outputs are wrong or missing\n\n{}\nRun with UPDATE_EXPECTED=1 to accept them",
+            failures.len(), failures.join("\n"));
+}
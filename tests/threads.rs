@@ -0,0 +1,91 @@
+//! Check that the evaluation gives the same outputs whatever the number of threads.
+//!
+//! A large sheet is generated with formulas reading each other over many levels,
+//! then evaluated and changed once on a single thread and once on several threads.
+
+use defi2::SpreadSheet;
+
+const ROWS: u32 = 400;
+const COLS: u32 = 10;
+
+/// Same numbers on every run, so both evaluations read the same sheet
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self, bound: u32) -> u32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % bound as u64) as u32
+    }
+}
+
+/// A column as written in the A1 notation
+fn column(col: u32) -> char {
+    (b'A' + col as u8) as char
+}
+
+/// Values, and formulas reading the rows above, some of them on a cycle or faulty
+fn data() -> String {
+    let mut random = Random(7);
+    let mut csv = String::new();
+    for row in 0..ROWS {
+        let values: Vec<String> = (0..COLS).map(|col| {
+            let above = row.saturating_sub(1 + random.next(20));
+            match random.next(8) {
+                _ if row == 0 => random.next(256).to_string(),
+                0 => format!("=#({},0,{},{},<{})", above, row - 1, COLS - 1, random.next(256)),
+                1 => format!("=SUM({},{},{},{})", above, col.saturating_sub(2), row - 1, col),
+                2 => format!("=MAX({}{}:{}{})", column(0), above + 1, column(COLS - 1), row),
+                3 => format!("={}{}-{}{}", column(col), above + 1, column(random.next(COLS)), row),
+                4 => format!("={}{}/{}", column(random.next(COLS)), above + 1, random.next(3)),
+                5 if row % 97 == 0 => format!("={}{}", column(col), row + 2),
+                _ => random.next(256).to_string(),
+            }
+        }).collect();
+        csv.push_str(&values.join(";"));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// User lines changing cells read by other formulas
+fn commands() -> Vec<String> {
+    let mut random = Random(11);
+    (0..10).map(|_| {
+        let (row, col) = (random.next(ROWS), random.next(COLS));
+        match random.next(3) {
+            0 => format!("{} {} ={}{}+1", row, col, column(random.next(COLS)), row + 3),
+            _ => format!("{} {} {}", row, col, random.next(256)),
+        }
+    }).collect()
+}
+
+/// Evaluate the sheet and apply the commands on a pool of this number of threads,
+/// and return the view and the changes
+fn run(threads: usize) -> (Vec<u8>, Vec<u8>) {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    pool.install(|| {
+        let mut sheet = SpreadSheet::new();
+        sheet.set_show_errors(true);
+        sheet.read_data(data().as_bytes()).unwrap();
+        sheet.evaluate();
+
+        let mut view = Vec::new();
+        sheet.write_view(&mut view).unwrap();
+
+        let mut changes = Vec::new();
+        for line in commands() {
+            sheet.apply_line(&line).unwrap();
+            let changed = sheet.take_changes();
+            sheet.write_changes(&line, &changed, &mut changes).unwrap();
+        }
+        (view, changes)
+    })
+}
+
+#[test]
+fn outputs_do_not_depend_on_the_number_of_threads() {
+    let (view, changes) = run(1);
+    let (parallel_view, parallel_changes) = run(4);
+    assert!(view == parallel_view, "the view differs with 4 threads");
+    assert!(changes == parallel_changes, "the changes differ with 4 threads");
+}